@@ -0,0 +1,196 @@
+use grid::Grid;
+use heatmap::HeatMap;
+use math::{Range, RangeBox};
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+// Written for None cells, and assumed when a file has no NODATA_value
+pub const NODATA: f32 = -9999.0;
+
+// Cells are treated as square when their sides differ by less than this fraction
+const SQUARE_TOLERANCE: f32 = 1e-6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsciiGridErr {
+    MissingHeader(&'static str),
+    InvalidHeader(String),
+    InvalidValue(String),
+    WrongValueCount { expected: usize, found: usize },
+}
+
+impl Display for AsciiGridErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AsciiGridErr::MissingHeader(name) => write!(f, "ASCII grid is missing {}", name),
+            AsciiGridErr::InvalidHeader(line) => {
+                write!(f, "Invalid ASCII grid header line \"{}\"", line)
+            }
+            AsciiGridErr::InvalidValue(value) => {
+                write!(f, "Invalid ASCII grid value \"{}\"", value)
+            }
+            AsciiGridErr::WrongValueCount { expected, found } => write!(
+                f,
+                "ASCII grid should have {} values but has {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl Error for AsciiGridErr {}
+
+// Writes an Arc/Info ASCII grid. The file lists rows north first while the grid's row 0 is the
+// southern edge of the range, so rows are written in reverse. Cells are centred on
+// range.from + index * cell size (see HeatMap::locate), so the corner is half a cell outside
+// the range. Non square cells are written with GDAL's dx and dy instead of cellsize
+pub fn write_ascii_grid(
+    path: impl AsRef<Path>,
+    grid: &Grid<Option<f32>>,
+    range: &RangeBox<f32>,
+) -> Result<(), Box<Error>> {
+    let cell_x = range.horizontal.length() / grid.horizontal as f32;
+    let cell_y = range.vertical.length() / grid.vertical as f32;
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "ncols {}", grid.horizontal)?;
+    writeln!(writer, "nrows {}", grid.vertical)?;
+    writeln!(writer, "xllcorner {}", range.horizontal.from - cell_x / 2.0)?;
+    writeln!(writer, "yllcorner {}", range.vertical.from - cell_y / 2.0)?;
+    if (cell_x - cell_y).abs() <= cell_x.abs() * SQUARE_TOLERANCE {
+        writeln!(writer, "cellsize {}", cell_x)?;
+    } else {
+        writeln!(writer, "dx {}", cell_x)?;
+        writeln!(writer, "dy {}", cell_y)?;
+    }
+    writeln!(writer, "NODATA_value {}", NODATA)?;
+    for y in (0..grid.vertical).rev() {
+        for x in 0..grid.horizontal {
+            if x > 0 {
+                write!(writer, " ")?;
+            }
+            match grid[[x, y]].filter(|value| !value.is_nan()) {
+                Some(value) => write!(writer, "{}", value)?,
+                None => write!(writer, "{}", NODATA)?,
+            }
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct Header {
+    ncols: Option<usize>,
+    nrows: Option<usize>,
+    // Left and bottom edge, or the centre of the bottom left cell
+    x: Option<(f32, bool)>,
+    y: Option<(f32, bool)>,
+    dx: Option<f32>,
+    dy: Option<f32>,
+    nodata: Option<f32>,
+}
+
+impl Header {
+    // Returns false for the first line that isn't a header line. Unknown keys are taken as the
+    // start of the data so rows starting with eg. "nan" aren't mistaken for the header
+    fn read_line(&mut self, line: &str) -> Result<bool, AsciiGridErr> {
+        let mut parts = line.split_whitespace();
+        let key = match parts.next() {
+            Some(key) => key.to_lowercase(),
+            None => return Ok(true),
+        };
+        let value = match parts.next() {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+        let invalid = || AsciiGridErr::InvalidHeader(String::from(line));
+        match key.as_str() {
+            "ncols" => self.ncols = Some(value.parse().map_err(|_| invalid())?),
+            "nrows" => self.nrows = Some(value.parse().map_err(|_| invalid())?),
+            "xllcorner" => self.x = Some((value.parse().map_err(|_| invalid())?, false)),
+            "xllcenter" => self.x = Some((value.parse().map_err(|_| invalid())?, true)),
+            "yllcorner" => self.y = Some((value.parse().map_err(|_| invalid())?, false)),
+            "yllcenter" => self.y = Some((value.parse().map_err(|_| invalid())?, true)),
+            "cellsize" => {
+                let size = value.parse().map_err(|_| invalid())?;
+                self.dx = Some(size);
+                self.dy = Some(size);
+            }
+            "dx" => self.dx = Some(value.parse().map_err(|_| invalid())?),
+            "dy" => self.dy = Some(value.parse().map_err(|_| invalid())?),
+            "nodata_value" => self.nodata = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+// Reads an Arc/Info ASCII grid. Values may be split across lines in any way, cells with the
+// NODATA_value or NaN are read as None
+pub fn read_ascii_grid(path: impl AsRef<Path>) -> Result<HeatMap<Option<f32>>, Box<Error>> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+
+    let mut header = Header::default();
+    let mut data_start = text.len();
+    let mut position = 0;
+    for line in text.split('\n') {
+        if !header.read_line(line)? {
+            data_start = position;
+            break;
+        }
+        position += line.len() + 1;
+    }
+    let width = header.ncols.ok_or(AsciiGridErr::MissingHeader("ncols"))?;
+    let height = header.nrows.ok_or(AsciiGridErr::MissingHeader("nrows"))?;
+    let (x, x_centre) = header.x.ok_or(AsciiGridErr::MissingHeader("xllcorner"))?;
+    let (y, y_centre) = header.y.ok_or(AsciiGridErr::MissingHeader("yllcorner"))?;
+    let cell_x = header.dx.ok_or(AsciiGridErr::MissingHeader("cellsize"))?;
+    let cell_y = header.dy.ok_or(AsciiGridErr::MissingHeader("cellsize"))?;
+    let nodata = header.nodata.unwrap_or(NODATA);
+
+    let mut values = vec![None; width * height];
+    let mut count = 0;
+    for token in text[data_start..].split_whitespace() {
+        let value: f32 = token
+            .parse()
+            .map_err(|_| AsciiGridErr::InvalidValue(String::from(token)))?;
+        if count < values.len() && !value.is_nan() && value != nodata {
+            // Rows are listed north first
+            let (column, row) = (count % width, count / width);
+            values[column + (height - 1 - row) * width] = Some(value);
+        }
+        count += 1;
+    }
+    if count != width * height {
+        return Err(Box::new(AsciiGridErr::WrongValueCount {
+            expected: width * height,
+            found: count,
+        }));
+    }
+
+    let from_x = if x_centre { x } else { x + cell_x / 2.0 };
+    let from_y = if y_centre { y } else { y + cell_y / 2.0 };
+    let range = RangeBox::new(
+        Range::new(from_x, from_x + width as f32 * cell_x),
+        Range::new(from_y, from_y + height as f32 * cell_y),
+    );
+    Ok(HeatMap::new(
+        Grid::new_from_values(width, height, values),
+        range,
+    ))
+}
+
+impl HeatMap<Option<f32>> {
+    pub fn from_ascii_grid(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        read_ascii_grid(path)
+    }
+
+    pub fn save_ascii_grid(&self, path: impl AsRef<Path>) -> Result<(), Box<Error>> {
+        write_ascii_grid(path, &self.grid, &self.range)
+    }
+}
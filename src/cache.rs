@@ -0,0 +1,187 @@
+use bincode::{deserialize_from, serialize_into};
+use data::{DataPoint, TemperaturePoint};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use units::Unit;
+
+// Caches start with the magic bytes, then the format version, the kind of value, the unit of the
+// values, the number of values and the values. Caches written before the header existed can't be told apart by their
+// layout, so they are rejected and have to be written again
+const MAGIC: [u8; 8] = *b"GSOMPNTS";
+pub const FORMAT_VERSION: u32 = 1;
+
+// Values that can be stored in a point cache. The kind is written to the header so a cache of
+// one value type isn't read as another
+pub trait CacheValue: Serialize + DeserializeOwned {
+    fn kind() -> &'static str;
+}
+
+impl CacheValue for TemperaturePoint {
+    fn kind() -> &'static str {
+        "TemperaturePoint"
+    }
+}
+
+impl CacheValue for DataPoint<f32> {
+    fn kind() -> &'static str {
+        "DataPoint<f32>"
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PointCacheErr {
+    // The file has no magic header, either it isn't a point cache or it was written before the
+    // header existed
+    NotAPointCache,
+    UnsupportedVersion(u32),
+    // The file holds different values than were asked for
+    WrongKind { expected: String, found: String },
+}
+
+impl Display for PointCacheErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PointCacheErr::NotAPointCache => write!(
+                f,
+                "File is not a point cache, caches written by older versions have to be rebuilt"
+            ),
+            PointCacheErr::UnsupportedVersion(version) => write!(
+                f,
+                "Point cache version {} is newer than the supported version {}",
+                version, FORMAT_VERSION
+            ),
+            PointCacheErr::WrongKind { expected, found } => write!(
+                f,
+                "Expected a point cache of {} but the file holds {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl Error for PointCacheErr {}
+
+// Writes points one at a time after the header. The number of values is written as 0 and filled
+// in by finish
+pub struct PointCacheWriter<T: CacheValue> {
+    writer: BufWriter<File>,
+    count: u64,
+    // Where the number of values is in the file
+    count_offset: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T: CacheValue> PointCacheWriter<T> {
+    // The unit is stored so maps loaded from the cache can't be combined with ones in another
+    pub fn create(path: impl AsRef<Path>, unit: Option<Unit>) -> Result<Self, Box<Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&MAGIC)?;
+        serialize_into(&mut writer, &FORMAT_VERSION)?;
+        serialize_into(&mut writer, T::kind())?;
+        serialize_into(&mut writer, &unit)?;
+        let count_offset = writer.stream_position()?;
+        serialize_into(&mut writer, &0u64)?;
+        Ok(Self {
+            writer,
+            count: 0,
+            count_offset,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn push(&mut self, value: &T) -> Result<(), Box<Error>> {
+        serialize_into(&mut self.writer, value)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    // Has to be called or the file will read as empty. Returns the number of values written
+    pub fn finish(mut self) -> Result<u64, Box<Error>> {
+        self.writer.flush()?;
+        let mut file = self.writer.into_inner().map_err(|err| err.into_error())?;
+        file.seek(SeekFrom::Start(self.count_offset))?;
+        serialize_into(&mut file, &self.count)?;
+        file.flush()?;
+        Ok(self.count)
+    }
+}
+
+// Reads the values of a point cache lazily instead of loading the whole Vec
+pub struct PointCacheReader<T: CacheValue> {
+    reader: BufReader<File>,
+    unit: Option<Unit>,
+    remaining: u64,
+    _marker: PhantomData<T>,
+}
+
+impl<T: CacheValue> PointCacheReader<T> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+            return Err(Box::new(PointCacheErr::NotAPointCache));
+        }
+        let version: u32 = deserialize_from(&mut reader)?;
+        if version > FORMAT_VERSION {
+            return Err(Box::new(PointCacheErr::UnsupportedVersion(version)));
+        }
+        let kind: String = deserialize_from(&mut reader)?;
+        if kind != T::kind() {
+            return Err(Box::new(PointCacheErr::WrongKind {
+                expected: String::from(T::kind()),
+                found: kind,
+            }));
+        }
+        let unit: Option<Unit> = deserialize_from(&mut reader)?;
+        let remaining: u64 = deserialize_from(&mut reader)?;
+        Ok(Self {
+            reader,
+            unit,
+            remaining,
+            _marker: PhantomData,
+        })
+    }
+
+    // Unit of the values, None if it wasn't known when the cache was written
+    pub fn unit(&self) -> Option<Unit> {
+        self.unit
+    }
+
+    pub fn len(&self) -> u64 {
+        self.remaining
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+impl<T: CacheValue> Iterator for PointCacheReader<T> {
+    type Item = Result<T, Box<Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        match deserialize_from(&mut self.reader) {
+            Ok(value) => Some(Ok(value)),
+            Err(err) => {
+                // The rest of the file can't be trusted after a failed read
+                self.remaining = 0;
+                Some(Err(From::from(err)))
+            }
+        }
+    }
+}
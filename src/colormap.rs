@@ -0,0 +1,368 @@
+use csv::Reader;
+use glium::backend::glutin::Display;
+use glium::texture::Texture1d;
+use math::Range;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display as FmtDisplay, Formatter};
+use std::mem;
+use std::path::Path;
+
+pub type Rgb = [f32; 3];
+
+// Number of colours in the 1D texture of continuous colormaps
+pub const LUT_SIZE: usize = 256;
+
+const VIRIDIS: [&str; 9] = [
+    "#440154", "#472d7b", "#3b528b", "#2c728e", "#21918c", "#28ae80", "#5ec962", "#addc30",
+    "#fde725",
+];
+const MAGMA: [&str; 10] = [
+    "#000004", "#180f3d", "#440f76", "#721f81", "#9e2f7f", "#cd4071", "#f1605d", "#fd9668",
+    "#feca8d", "#fcfdbf",
+];
+const CIVIDIS: [&str; 10] = [
+    "#00224e", "#123570", "#3b496c", "#575d6d", "#707173", "#8a8678", "#a59c74", "#c3b369",
+    "#e1cc55", "#fee838",
+];
+const RD_BU: [&str; 11] = [
+    "#67001f", "#b2182b", "#d6604d", "#f4a582", "#fddbc7", "#f7f7f7", "#d1e5f0", "#92c5de",
+    "#4393c3", "#2166ac", "#053061",
+];
+const TAB10: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf",
+];
+const SET1: [&str; 9] = [
+    "#e41a1c", "#377eb8", "#4daf4a", "#984ea3", "#ff7f00", "#ffff33", "#a65628", "#f781bf",
+    "#999999",
+];
+// hsv_to_rgb of the shaders, which is sampled with 1 - value so it runs from magenta to red
+const RAINBOW: [&str; 6] = [
+    "#ff00ff", "#0000ff", "#00ffff", "#00ff00", "#ffff00", "#ff0000",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ColormapKind {
+    // Low to high values, eg. temperature
+    Sequential,
+    // Values either side of a midpoint, eg. anomalies
+    Diverging,
+    // Distinct classes without an order, colours are not blended
+    Qualitative,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColormapErr {
+    Unknown(String),
+    InvalidColour(String),
+    // Positions have to be finite and in increasing order
+    InvalidStops(String),
+}
+
+impl FmtDisplay for ColormapErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ColormapErr::Unknown(name) => write!(f, "Unknown colormap \"{}\"", name),
+            ColormapErr::InvalidColour(colour) => write!(f, "Invalid colour \"{}\"", colour),
+            ColormapErr::InvalidStops(reason) => write!(f, "Invalid colormap stops: {}", reason),
+        }
+    }
+}
+
+impl Error for ColormapErr {}
+
+// Parses "#rrggbb" or "rrggbb"
+pub fn parse_hex(colour: &str) -> Result<Rgb, ColormapErr> {
+    let hex = colour.trim().trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+            .map(|channel| f32::from(channel) / 255.0)
+            .ok_or_else(|| ColormapErr::InvalidColour(String::from(colour)))
+    };
+    if hex.len() != 6 {
+        return Err(ColormapErr::InvalidColour(String::from(colour)));
+    }
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// Row of a colormap file
+#[derive(Debug, Deserialize)]
+struct StopRow {
+    #[serde(rename = "POSITION")]
+    position: f32,
+    #[serde(rename = "COLOUR")]
+    colour: String,
+}
+
+// Maps values between 0 and 1 to colours. Continuous colormaps blend between their stops,
+// qualitative ones use the colour of the last stop at or below the value
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Colormap {
+    pub name: String,
+    pub kind: ColormapKind,
+    stops: Vec<(f32, Rgb)>,
+    // Colours below 0 and above 1, the end colours when not set
+    pub under: Option<Rgb>,
+    pub over: Option<Rgb>,
+    pub nodata: Rgb,
+}
+
+impl Colormap {
+    pub fn new(name: &str, kind: ColormapKind, stops: &[(f32, Rgb)]) -> Result<Self, ColormapErr> {
+        let required = match kind {
+            ColormapKind::Qualitative => 1,
+            _ => 2,
+        };
+        if stops.len() < required {
+            return Err(ColormapErr::InvalidStops(format!(
+                "needs at least {} stops",
+                required
+            )));
+        }
+        for pair in stops.windows(2) {
+            if !pair[0].0.is_finite() || !pair[1].0.is_finite() || pair[1].0 < pair[0].0 {
+                return Err(ColormapErr::InvalidStops(String::from(
+                    "positions are not in increasing order",
+                )));
+            }
+        }
+        Ok(Self {
+            name: String::from(name),
+            kind,
+            stops: stops.to_vec(),
+            under: None,
+            over: None,
+            nodata: [0.0, 0.0, 0.0],
+        })
+    }
+
+    // Spreads the colours evenly from 0 to 1. Qualitative classes each get an equal share
+    pub fn from_colours(
+        name: &str,
+        kind: ColormapKind,
+        colours: &[Rgb],
+    ) -> Result<Self, ColormapErr> {
+        let steps = match kind {
+            ColormapKind::Qualitative => colours.len(),
+            _ => colours.len().saturating_sub(1).max(1),
+        };
+        let stops: Vec<(f32, Rgb)> = colours
+            .iter()
+            .enumerate()
+            .map(|(i, &colour)| (i as f32 / steps as f32, colour))
+            .collect();
+        Self::new(name, kind, &stops)
+    }
+
+    pub fn from_hex(name: &str, kind: ColormapKind, colours: &[&str]) -> Result<Self, ColormapErr> {
+        let colours = colours
+            .iter()
+            .map(|colour| parse_hex(colour))
+            .collect::<Result<Vec<Rgb>, ColormapErr>>()?;
+        Self::from_colours(name, kind, &colours)
+    }
+
+    // Reads a continuous colormap from a csv of control points, eg.
+    //   POSITION,COLOUR
+    //   0.0,#0000ff
+    //   0.5,#ffffff
+    //   1.0,#ff0000
+    pub fn load(path: impl AsRef<Path>, kind: ColormapKind) -> Result<Self, Box<Error>> {
+        let name = path
+            .as_ref()
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("custom")
+            .to_string();
+        let mut reader = Reader::from_path(path)?;
+        let mut stops = Vec::new();
+        for row in reader.deserialize() {
+            let row: StopRow = row?;
+            stops.push((row.position, parse_hex(&row.colour)?));
+        }
+        Ok(Self::new(&name, kind, &stops)?)
+    }
+
+    fn builtin(name: &str, kind: ColormapKind, colours: &[&str]) -> Self {
+        Self::from_hex(name, kind, colours).expect("Invalid built in colormap")
+    }
+
+    pub fn viridis() -> Self {
+        Self::builtin("viridis", ColormapKind::Sequential, &VIRIDIS)
+    }
+
+    pub fn magma() -> Self {
+        Self::builtin("magma", ColormapKind::Sequential, &MAGMA)
+    }
+
+    pub fn cividis() -> Self {
+        Self::builtin("cividis", ColormapKind::Sequential, &CIVIDIS)
+    }
+
+    pub fn greys() -> Self {
+        Self::builtin("greys", ColormapKind::Sequential, &["#ffffff", "#000000"])
+    }
+
+    pub fn rd_bu() -> Self {
+        Self::builtin("RdBu", ColormapKind::Diverging, &RD_BU)
+    }
+
+    pub fn tab10() -> Self {
+        Self::builtin("tab10", ColormapKind::Qualitative, &TAB10)
+    }
+
+    pub fn set1() -> Self {
+        Self::builtin("Set1", ColormapKind::Qualitative, &SET1)
+    }
+
+    // The hue ramp of fragment.glsl
+    pub fn rainbow() -> Self {
+        Self::builtin("rainbow", ColormapKind::Sequential, &RAINBOW)
+    }
+
+    pub fn names() -> [&'static str; 8] {
+        [
+            "viridis", "magma", "cividis", "greys", "RdBu", "tab10", "Set1", "rainbow",
+        ]
+    }
+
+    // Case insensitive, a "_r" suffix reverses the colormap
+    pub fn named(name: &str) -> Result<Self, ColormapErr> {
+        let lower = name.to_lowercase();
+        if lower.ends_with("_r") {
+            return Ok(Self::named(&name[..name.len() - 2])?.reversed());
+        }
+        let colormap = match lower.as_str() {
+            "viridis" => Self::viridis(),
+            "magma" => Self::magma(),
+            "cividis" => Self::cividis(),
+            "greys" | "grays" => Self::greys(),
+            "rdbu" => Self::rd_bu(),
+            "tab10" => Self::tab10(),
+            "set1" => Self::set1(),
+            "rainbow" => Self::rainbow(),
+            _ => return Err(ColormapErr::Unknown(String::from(name))),
+        };
+        Ok(colormap)
+    }
+
+    pub fn with_under(mut self, colour: Rgb) -> Self {
+        self.under = Some(colour);
+        self
+    }
+
+    pub fn with_over(mut self, colour: Rgb) -> Self {
+        self.over = Some(colour);
+        self
+    }
+
+    pub fn with_nodata(mut self, colour: Rgb) -> Self {
+        self.nodata = colour;
+        self
+    }
+
+    pub fn reversed(mut self) -> Self {
+        let (first, last) = (self.stops[0].0, self.stops[self.stops.len() - 1].0);
+        let colours: Vec<Rgb> = self.stops.iter().rev().map(|stop| stop.1).collect();
+        match self.kind {
+            // Stops are where classes start, so the positions stay and the colours flip
+            ColormapKind::Qualitative => {
+                for (stop, colour) in self.stops.iter_mut().zip(colours) {
+                    stop.1 = colour;
+                }
+            }
+            _ => {
+                self.stops = self
+                    .stops
+                    .iter()
+                    .rev()
+                    .map(|&(position, colour)| (first + last - position, colour))
+                    .collect()
+            }
+        }
+        mem::swap(&mut self.under, &mut self.over);
+        self.name = format!("{}_r", self.name);
+        self
+    }
+
+    pub fn stops(&self) -> &[(f32, Rgb)] {
+        &self.stops
+    }
+
+    pub fn under_colour(&self) -> Rgb {
+        self.under.unwrap_or_else(|| self.stops[0].1)
+    }
+
+    pub fn over_colour(&self) -> Rgb {
+        self.over
+            .unwrap_or_else(|| self.stops[self.stops.len() - 1].1)
+    }
+
+    // Colour at position t, values outside 0 to 1 get the under and over colours
+    pub fn sample(&self, t: f32) -> Rgb {
+        if t.is_nan() {
+            return self.nodata;
+        }
+        if t < 0.0 {
+            return self.under_colour();
+        }
+        if t > 1.0 {
+            return self.over_colour();
+        }
+        let next = self.stops.iter().position(|stop| stop.0 > t);
+        match (self.kind, next) {
+            (_, Some(0)) => self.stops[0].1,
+            (_, None) => self.stops[self.stops.len() - 1].1,
+            (ColormapKind::Qualitative, Some(i)) => self.stops[i - 1].1,
+            (_, Some(i)) => {
+                let (from, to) = (self.stops[i - 1], self.stops[i]);
+                let mix = (t - from.0) / (to.0 - from.0);
+                [
+                    from.1[0] + (to.1[0] - from.1[0]) * mix,
+                    from.1[1] + (to.1[1] - from.1[1]) * mix,
+                    from.1[2] + (to.1[2] - from.1[2]) * mix,
+                ]
+            }
+        }
+    }
+
+    // Colour of a value in the range, missing values get the nodata colour
+    pub fn colour(&self, value: Option<f32>, range: Range<f32>) -> Rgb {
+        match value {
+            Some(value) if range.length() != 0.0 => {
+                self.sample((value - range.from) / range.length())
+            }
+            Some(_) => self.sample(0.5),
+            None => self.nodata,
+        }
+    }
+
+    // Colours sampled at the centre of size equal steps, entry min(t * size, size - 1) is the
+    // colour of t. Used as the shaders' 1D palette texture
+    pub fn lut(&self, size: usize) -> Vec<Rgb> {
+        (0..size)
+            .map(|i| self.sample((i as f32 + 0.5) / size as f32))
+            .collect()
+    }
+
+    // Qualitative colormaps get one texel per class so the classes don't blend
+    pub fn lut_size(&self) -> usize {
+        match self.kind {
+            ColormapKind::Qualitative => self.stops.len(),
+            _ => LUT_SIZE,
+        }
+    }
+
+    // Palette for shaders/colormap.glsl and shaders/colormap_gradient.glsl, pass it as the
+    // palette uniform along with under_colour, over_colour and nodata_colour
+    pub fn texture(&self, display: &Display) -> Texture1d {
+        let lut: Vec<(f32, f32, f32)> = self
+            .lut(self.lut_size())
+            .into_iter()
+            .map(|colour| (colour[0], colour[1], colour[2]))
+            .collect();
+        Texture1d::new(display, lut).expect("Failed to create texture")
+    }
+}
@@ -0,0 +1,293 @@
+use colormap::Colormap;
+use graticule::Graticule;
+use grid::Grid;
+use image;
+use image::GenericImage;
+use image::RgbaImage;
+use legend::Legend;
+use math::{Range, RangeBox};
+use rayon::prelude::*;
+use std::error::Error;
+use std::path::Path;
+use vector::VectorLayer;
+
+pub const BASEMAP_PATH: &str = "Pure B and W Map.png";
+
+// Draws the vectors in order and the graticule over them. Shared by MapRenderer and the viewer
+// in helper::show_heat_map so both draw overlays the same way
+pub fn draw_overlays(
+    image: &mut RgbaImage,
+    range: &RangeBox<f32>,
+    vectors: &[VectorLayer],
+    graticule: Option<&Graticule>,
+) {
+    for layer in vectors {
+        layer.draw(image, range);
+    }
+    if let Some(graticule) = graticule {
+        graticule.draw(image, range);
+    }
+}
+
+// Same ramp as hsv_to_rgb in fragment.glsl, red at 0 through to magenta at 1
+pub fn hue_ramp(hue: f32) -> [f32; 3] {
+    let h = hue * 100.0;
+    let x = 1.0 - ((h / 20.0) % 2.0 - 1.0).abs();
+    if (0.0..20.0).contains(&h) {
+        [1.0, x, 0.0]
+    } else if (20.0..40.0).contains(&h) {
+        [x, 1.0, 0.0]
+    } else if (40.0..60.0).contains(&h) {
+        [0.0, 1.0, x]
+    } else if (60.0..80.0).contains(&h) {
+        [0.0, x, 1.0]
+    } else if (80.0..100.0).contains(&h) {
+        [x, 0.0, 1.0]
+    } else if h > 100.0 {
+        [1.0, 0.0, 1.0]
+    } else {
+        [1.0, 0.0, 0.0]
+    }
+}
+
+// Contrast adjustment of fragment.glsl, contrast goes from -255 to 255 with 0 leaving values as
+// they are
+pub fn contrast_factor(contrast: f32) -> f32 {
+    (259.0 * (contrast + 255.0)) / (255.0 * (259.0 - contrast))
+}
+
+// Renders grids to images without OpenGL, matching what fragment.glsl draws in the map box
+pub struct MapRenderer {
+    pub width: u32,
+    pub height: u32,
+    pub contrast: f32,
+    // Values at the ends of the hue ramp, the grid's minimum and maximum when not set
+    pub value_range: Option<Range<f32>>,
+    // Draws with the colormap as shaders/colormap.glsl does instead of the hue ramp
+    pub colormap: Option<Colormap>,
+    // Coastlines, borders and so on, drawn in order under the graticule
+    pub vectors: Vec<VectorLayer>,
+    pub graticule: Option<Graticule>,
+    // World map covering -180 to 180 and -90 to 90, multiplied into the colours
+    pub basemap: Option<RgbaImage>,
+}
+
+impl MapRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            contrast: 0.0,
+            value_range: None,
+            colormap: None,
+            vectors: Vec::new(),
+            graticule: None,
+            basemap: None,
+        }
+    }
+
+    pub fn with_contrast(mut self, contrast: f32) -> Self {
+        self.contrast = contrast;
+        self
+    }
+
+    pub fn with_value_range(mut self, range: Range<f32>) -> Self {
+        self.value_range = Some(range);
+        self
+    }
+
+    pub fn with_colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = Some(colormap);
+        self
+    }
+
+    pub fn with_vectors(mut self, layer: VectorLayer) -> Self {
+        self.vectors.push(layer);
+        self
+    }
+
+    pub fn with_graticule(mut self, graticule: Graticule) -> Self {
+        self.graticule = Some(graticule);
+        self
+    }
+
+    pub fn with_basemap(mut self, basemap: RgbaImage) -> Self {
+        self.basemap = Some(basemap);
+        self
+    }
+
+    pub fn load_basemap(self, path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let basemap = image::open(path)?.to_rgba();
+        Ok(self.with_basemap(basemap))
+    }
+
+    // Value in the range [-1, 1] the shader reads from the texture made by Grid::into_texture.
+    // Missing cells are -1 and values outside the range are clamped as the texture would be
+    fn texture_value(value: Option<f32>, min: f32, max: f32) -> f32 {
+        let stored = match value {
+            Some(value) if max > min => ((value - min) / (max - min) + 1.0) * 0.5,
+            // Every value is the same, they are drawn in the middle of the ramp
+            Some(_) => 0.75,
+            None => 0.0,
+        };
+        (stored.clamp(0.0, 1.0) - 0.5) * 2.0
+    }
+
+    fn hue_colour(value: Option<f32>, min: f32, max: f32, contrast: f32) -> [f32; 3] {
+        let value = Self::texture_value(value, min, max);
+        let brightness = contrast * (value - 0.5) + 0.5;
+        let hue = hue_ramp(1.0 - brightness);
+        // Missing and below range cells fade to black
+        let fade = -value.min(0.0);
+        [
+            hue[0] * (1.0 - fade),
+            hue[1] * (1.0 - fade),
+            hue[2] * (1.0 - fade),
+        ]
+    }
+
+    // Same as shaders/colormap.glsl with the texture from Grid::into_normalised_texture
+    fn colormap_colour(
+        colormap: &Colormap,
+        value: Option<f32>,
+        min: f32,
+        max: f32,
+        contrast: f32,
+    ) -> [f32; 3] {
+        match value {
+            Some(value) => {
+                let t = if max != min {
+                    (value - min) / (max - min)
+                } else {
+                    0.5
+                };
+                colormap.sample(contrast * (t - 0.5) + 0.5)
+            }
+            None => colormap.nodata,
+        }
+    }
+
+    // Basemap brightness at the texture coordinate of the map box
+    fn basemap_value(&self, range: &RangeBox<f32>, tex_x: f32, tex_y: f32) -> f32 {
+        let basemap = match self.basemap {
+            Some(ref basemap) => basemap,
+            None => return 1.0,
+        };
+        let (width, height) = basemap.dimensions();
+        let min_x = (range.horizontal.from + 180.0) / 360.0;
+        let max_x = (range.horizontal.to + 180.0) / 360.0;
+        let min_y = (range.vertical.from + 90.0) / 180.0;
+        let max_y = (range.vertical.to + 90.0) / 180.0;
+        let x = width as f32 * (min_x + (max_x - min_x) * tex_x);
+        let y = height as f32 * (min_y + (max_y - min_y) * tex_y);
+        let x = (x as i64).clamp(0, i64::from(width) - 1) as u32;
+        // Image rows start at the top, texture rows at the bottom
+        let y = (y as i64).clamp(0, i64::from(height) - 1) as u32;
+        f32::from(basemap.get_pixel(x, height - 1 - y).data[0]) / 255.0
+    }
+
+    fn value_bounds(&self, grid: &Grid<Option<f32>>) -> (f32, f32) {
+        match self.value_range {
+            Some(value_range) => (value_range.from, value_range.to),
+            None => (
+                grid.min_option().unwrap_or(0.0),
+                grid.max_option().unwrap_or(0.0),
+            ),
+        }
+    }
+
+    // Colormap the map is drawn with, the hue ramp is the rainbow colormap with missing cells
+    // in black
+    pub fn display_colormap(&self) -> Colormap {
+        match self.colormap {
+            Some(ref colormap) => colormap.clone(),
+            None => Colormap::rainbow()
+                .with_under([0.0, 0.0, 0.0])
+                .with_nodata([0.0, 0.0, 0.0]),
+        }
+    }
+
+    // Legend matching the colours the grid is drawn with, in the grid's units
+    pub fn legend(&self, grid: &Grid<Option<f32>>) -> Legend {
+        let (min, max) = self.value_bounds(grid);
+        Legend::new(self.display_colormap(), Range::new(min, max))
+            .with_contrast(self.contrast)
+            .with_unit(grid.unit)
+            .with_bar_length(self.height / 2)
+    }
+
+    pub fn render(&self, grid: &Grid<Option<f32>>, range: &RangeBox<f32>) -> RgbaImage {
+        let (min, max) = self.value_bounds(grid);
+        let contrast = contrast_factor(self.contrast);
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut pixels = vec![0u8; width * height * 4];
+        if grid.values.is_empty() || width == 0 {
+            return RgbaImage::from_raw(self.width, self.height, pixels).unwrap();
+        }
+        pixels
+            .par_chunks_mut(width * 4)
+            .enumerate()
+            .for_each(|(row, pixels)| {
+                // Texture coordinates of the pixel centre, y = 0 is the bottom of the map
+                let tex_y = 1.0 - (row as f32 + 0.5) / height as f32;
+                let y = ((grid.vertical as f32 * tex_y) as usize).min(grid.vertical - 1);
+                for (column, pixel) in pixels.chunks_mut(4).enumerate() {
+                    let tex_x = (column as f32 + 0.5) / width as f32;
+                    let x = ((grid.horizontal as f32 * tex_x) as usize).min(grid.horizontal - 1);
+                    let colour = match self.colormap {
+                        Some(ref colormap) => {
+                            Self::colormap_colour(colormap, grid[[x, y]], min, max, contrast)
+                        }
+                        None => Self::hue_colour(grid[[x, y]], min, max, contrast),
+                    };
+                    let base = self.basemap_value(range, tex_x, tex_y);
+                    for channel in 0..3 {
+                        let colour = base * colour[channel];
+                        pixel[channel] = (colour.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    }
+                    pixel[3] = 255;
+                }
+            });
+        let mut image = RgbaImage::from_raw(self.width, self.height, pixels).unwrap();
+        draw_overlays(&mut image, range, &self.vectors, self.graticule.as_ref());
+        image
+    }
+
+    pub fn save_png(
+        &self,
+        grid: &Grid<Option<f32>>,
+        range: &RangeBox<f32>,
+        path: impl AsRef<Path>,
+    ) -> Result<(), Box<Error>> {
+        self.render(grid, range).save(path)?;
+        Ok(())
+    }
+
+    // Map with the legend to its right, centred vertically
+    pub fn render_with_legend(
+        &self,
+        grid: &Grid<Option<f32>>,
+        range: &RangeBox<f32>,
+        legend: &Legend,
+    ) -> RgbaImage {
+        let map = self.render(grid, range);
+        let key = legend.render();
+        let width = map.width() + key.width();
+        let height = map.height().max(key.height());
+        let mut image = RgbaImage::from_pixel(width, height, legend.background);
+        image.copy_from(&map, 0, (height - map.height()) / 2);
+        image.copy_from(&key, map.width(), (height - key.height()) / 2);
+        image
+    }
+
+    pub fn save_png_with_legend(
+        &self,
+        grid: &Grid<Option<f32>>,
+        range: &RangeBox<f32>,
+        legend: &Legend,
+        path: impl AsRef<Path>,
+    ) -> Result<(), Box<Error>> {
+        self.render_with_legend(grid, range, legend).save(path)?;
+        Ok(())
+    }
+}
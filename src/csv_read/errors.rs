@@ -100,3 +100,88 @@ impl Display for IncorrectColumnsErr {
 }
 
 impl Error for IncorrectColumnsErr {}
+
+#[derive(Clone, Debug)]
+pub struct FixedWidthErr {
+    field: String,
+    line: String,
+}
+
+impl FixedWidthErr {
+    pub fn new(field: &str, line: &str) -> Self {
+        Self {
+            field: String::from(field),
+            line: String::from(line),
+        }
+    }
+}
+
+impl Display for FixedWidthErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Could not read field {} from line \"{}\"", self.field, self.line)
+    }
+}
+
+impl Error for FixedWidthErr {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DateErrKind {
+    // Not in the form YYYY-MM, YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS]
+    Format,
+    Month(u32),
+    Day(u32),
+    Time,
+}
+
+#[derive(Clone, Debug)]
+pub struct DateParseErr {
+    input: String,
+    kind: DateErrKind,
+}
+
+impl DateParseErr {
+    pub fn new(input: &str, kind: DateErrKind) -> Self {
+        Self {
+            input: String::from(input),
+            kind,
+        }
+    }
+
+    pub fn kind(&self) -> &DateErrKind {
+        &self.kind
+    }
+}
+
+impl Display for DateParseErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.kind {
+            DateErrKind::Format => write!(f, "Date \"{}\" is not in a known format", self.input),
+            DateErrKind::Month(month) => {
+                write!(f, "Date \"{}\" has invalid month {}", self.input, month)
+            }
+            DateErrKind::Day(day) => write!(f, "Date \"{}\" has invalid day {}", self.input, day),
+            DateErrKind::Time => write!(f, "Date \"{}\" has an invalid time", self.input),
+        }
+    }
+}
+
+impl Error for DateParseErr {}
+
+#[derive(Clone, Debug)]
+pub struct SchemaErr {
+    message: String,
+}
+
+impl SchemaErr {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl Display for SchemaErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid schema: {}", self.message)
+    }
+}
+
+impl Error for SchemaErr {}
@@ -0,0 +1,322 @@
+use csv_read::errors::FixedWidthErr;
+use csv_read::read::{days_in_month, Date, ElementAttributes};
+use data::{DataPoint, TemperaturePoint};
+use ingest::{IngestFilter, QualityFilter};
+use math::Point;
+use station::{StationMeta, StationTable};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+// GHCN-Daily uses -9999 for days without a value, including days that do not exist in the month
+const MISSING_VALUE: i32 = -9999;
+const DAYS_PER_RECORD: usize = 31;
+
+// Returns the trimmed text in the 1 based inclusive column range used by the GHCN-D readme
+fn columns<'a>(
+    line: &'a str,
+    from: usize,
+    to: usize,
+    field: &str,
+) -> Result<&'a str, FixedWidthErr> {
+    match line.get(from - 1..to) {
+        Some(text) => Ok(text.trim()),
+        None => Err(FixedWidthErr::new(field, line)),
+    }
+}
+
+fn parse_columns<T: FromStr>(
+    line: &str,
+    from: usize,
+    to: usize,
+    field: &str,
+) -> Result<T, FixedWidthErr> {
+    columns(line, from, to, field)?
+        .parse()
+        .map_err(|_| FixedWidthErr::new(field, line))
+}
+
+fn flag(text: &str) -> Option<char> {
+    text.chars().next().filter(|&c| c != ' ')
+}
+
+// Multiplier that converts the stored integer of an element into its documented unit
+// eg. TMAX is stored in tenths of degrees C
+pub fn element_scale(element: &str) -> f32 {
+    match element {
+        "TMAX" | "TMIN" | "TAVG" | "TOBS" | "PRCP" | "AWND" | "EVAP" | "MNPN" | "MXPN" | "WESD"
+        | "WESF" | "THIC" | "ADPT" | "AWBT" | "WSF2" | "WSF5" | "WSFG" | "WSFI" => 0.1,
+        _ => 1.0,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct DailyValue {
+    pub day: u32,
+    // Already scaled with element_scale
+    pub value: f32,
+    pub flags: ElementAttributes,
+}
+
+// One line of a .dly file, a month of values for a single element
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DailyRecord {
+    pub station: String,
+    pub year: u32,
+    pub month: u32,
+    pub element: String,
+    // Only the days that have a value
+    pub values: Vec<DailyValue>,
+}
+
+impl FromStr for DailyRecord {
+    type Err = FixedWidthErr;
+
+    // ID 1-11, YEAR 12-15, MONTH 16-17, ELEMENT 18-21 then 31 groups of
+    // VALUE (5), MFLAG (1), QFLAG (1), SFLAG (1)
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let station = String::from(columns(line, 1, 11, "ID")?);
+        let year = parse_columns(line, 12, 15, "YEAR")?;
+        let month = parse_columns(line, 16, 17, "MONTH")?;
+        if Date::new(year, month, None).is_err() {
+            return Err(FixedWidthErr::new("MONTH", line));
+        }
+        let element = String::from(columns(line, 18, 21, "ELEMENT")?);
+        let scale = element_scale(&element);
+
+        let mut values = Vec::with_capacity(DAYS_PER_RECORD);
+        for day in 0..DAYS_PER_RECORD {
+            let start = 22 + day * 8;
+            let value: i32 = parse_columns(line, start, start + 4, "VALUE")?;
+            if value == MISSING_VALUE {
+                continue;
+            }
+            // Trailing blank flags are sometimes trimmed from the end of the line
+            let flags = line.get(start + 4..start + 7).unwrap_or("");
+            values.push(DailyValue {
+                day: day as u32 + 1,
+                value: value as f32 * scale,
+                flags: ElementAttributes {
+                    missing_days: None,
+                    measurement: flags.get(0..1).and_then(flag),
+                    quality: flags.get(1..2).and_then(flag),
+                    source: flags.get(2..3).and_then(flag),
+                },
+            });
+        }
+
+        Ok(Self {
+            station,
+            year,
+            month,
+            element,
+            values,
+        })
+    }
+}
+
+// Reads every record of a .dly file whose element is in elements, or all records if elements is
+// empty
+pub fn read_dly<P: AsRef<Path>>(
+    path: P,
+    elements: &[&str],
+) -> Result<Vec<DailyRecord>, Box<Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !elements.is_empty() {
+            let element = columns(&line, 18, 21, "ELEMENT")?;
+            if !elements.contains(&element) {
+                continue;
+            }
+        }
+        records.push(line.parse()?);
+    }
+    Ok(records)
+}
+
+// A line of ghcnd-stations.txt
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GhcndStation {
+    pub id: String,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub elevation: Option<f32>,
+    pub state: Option<String>,
+    pub name: String,
+}
+
+impl GhcndStation {
+    pub fn position(&self) -> Point<f32> {
+        Point::new(self.longitude, self.latitude)
+    }
+
+    pub fn meta(&self) -> StationMeta {
+        StationMeta {
+            id: self.id.clone(),
+            name: Some(self.name.clone()),
+            position: Some(self.position()),
+            elevation: self.elevation,
+        }
+    }
+}
+
+impl FromStr for GhcndStation {
+    type Err = FixedWidthErr;
+
+    // ID 1-11, LATITUDE 13-20, LONGITUDE 22-30, ELEVATION 32-37, STATE 39-40, NAME 42-71
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let elevation: f32 = parse_columns(line, 32, 37, "ELEVATION")?;
+        let state = line.get(38..40).map(|state| state.trim()).unwrap_or("");
+        let name = line.get(41..71).or_else(|| line.get(41..)).unwrap_or("");
+        Ok(Self {
+            id: String::from(columns(line, 1, 11, "ID")?),
+            latitude: parse_columns(line, 13, 20, "LATITUDE")?,
+            longitude: parse_columns(line, 22, 30, "LONGITUDE")?,
+            // -999.9 marks a missing elevation
+            elevation: if elevation <= -999.0 {
+                None
+            } else {
+                Some(elevation)
+            },
+            state: if state.is_empty() {
+                None
+            } else {
+                Some(String::from(state))
+            },
+            name: String::from(name.trim()),
+        })
+    }
+}
+
+pub fn read_station_inventory<P: AsRef<Path>>(
+    path: P,
+) -> Result<HashMap<String, GhcndStation>, Box<Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut stations = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let station: GhcndStation = line.parse()?;
+        stations.insert(station.id.clone(), station);
+    }
+    Ok(stations)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MonthlyStatistic {
+    // Used for temperatures
+    Mean,
+    // Used for totals such as PRCP and SNOW
+    Sum,
+}
+
+// Rolls daily records of one element up into monthly TemperaturePoints. Daily values are
+// filtered with the quality filter, and the month is then filtered with the number of days
+// that had no accepted value as its missing days
+#[derive(Debug, Clone)]
+pub struct MonthlyRollup {
+    pub element: String,
+    pub statistic: MonthlyStatistic,
+    pub quality: QualityFilter,
+    pub filter: IngestFilter,
+}
+
+impl MonthlyRollup {
+    pub fn new(element: &str, statistic: MonthlyStatistic) -> Self {
+        Self {
+            element: String::from(element),
+            statistic,
+            quality: QualityFilter::default(),
+            filter: IngestFilter::default(),
+        }
+    }
+
+    pub fn with_quality(mut self, quality: QualityFilter) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: IngestFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    fn accepts(&self, record: &DailyRecord, station: &GhcndStation) -> bool {
+        let filter = &self.filter;
+        filter
+            .check_date(Some(record.year), Some(record.month))
+            .and_then(|_| filter.check_position(Some(station.position())))
+            .and_then(|_| filter.check_elevation(station.elevation))
+            .and_then(|_| filter.check_station(Some(&station.id)))
+            .is_ok()
+    }
+
+    pub fn monthly_value(&self, record: &DailyRecord) -> Option<f32> {
+        let mut sum = 0.0;
+        let mut count = 0;
+        for day in &record.values {
+            if self.quality.accepts(Some(day.flags)) {
+                sum += day.value;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return None;
+        }
+        let month_attributes = ElementAttributes {
+            missing_days: Some(days_in_month(record.year, record.month).saturating_sub(count)),
+            ..Default::default()
+        };
+        if !self.quality.accepts(Some(month_attributes)) {
+            return None;
+        }
+        match self.statistic {
+            MonthlyStatistic::Mean => Some(sum / count as f32),
+            MonthlyStatistic::Sum => Some(sum),
+        }
+    }
+
+    // Stations missing from the inventory are skipped as their coordinates are unknown, as are
+    // records the filter rejects. Every station that is used is added to the station table
+    pub fn points(
+        &self,
+        records: &[DailyRecord],
+        inventory: &HashMap<String, GhcndStation>,
+        stations: &mut StationTable,
+    ) -> Vec<TemperaturePoint> {
+        let mut points = Vec::new();
+        for record in records {
+            if record.element != self.element {
+                continue;
+            }
+            let station = match inventory.get(&record.station) {
+                Some(station) => station,
+                None => continue,
+            };
+            if !self.accepts(record, station) {
+                continue;
+            }
+            let value = match self.monthly_value(record) {
+                Some(value) => value,
+                None => continue,
+            };
+            let index = stations.insert(station.meta());
+            let point = TemperaturePoint::new(
+                record.month as usize,
+                DataPoint::new(station.position(), value),
+            );
+            points.push(point.with_year(record.year).with_station(index));
+        }
+        points
+    }
+}
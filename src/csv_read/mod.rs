@@ -1,13 +1,17 @@
-use self::errors::{HeaderAddErr, HeaderContainerErr, ColumnMissing};
+use self::errors::{ColumnMissing, HeaderAddErr, HeaderContainerErr, IncorrectColumnsErr};
+use csv;
 use csv::{StringRecord, StringRecordsIter};
 use std::cmp::PartialEq;
 use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::io::Read;
+use std::marker::PhantomData;
 
 pub mod errors;
+pub mod ghcnd;
 pub mod read;
+pub mod schema;
 
 #[derive(Debug, Clone)]
 pub struct Header {
@@ -103,13 +107,100 @@ pub fn header_contains(headers: &StringRecord, name: &str) -> Option<usize> {
     None
 }
 
+// Case-insensitive match against any of the aliases
+pub fn header_contains_alias(headers: &StringRecord, aliases: &[String]) -> Option<usize> {
+    for (i, header) in headers.iter().enumerate() {
+        let header = header.trim();
+        if aliases.iter().any(|alias| alias.eq_ignore_ascii_case(header)) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+// Turns a single csv record into a value. Ok(None) means the record is skipped
+pub trait RecordParser {
+    type Output;
+    fn parse(&self, record: &StringRecord) -> Result<Option<Self::Output>, Box<Error>>;
+}
+
+// Lazily parses records as they are read from the csv, so only one record is held in memory
+// at a time
+pub struct RecordIter<I, P> {
+    records: I,
+    parser: P,
+}
+
+impl<I, P> RecordIter<I, P> {
+    pub fn new(records: I, parser: P) -> Self {
+        Self { records, parser }
+    }
+}
+
+impl<I, P> Iterator for RecordIter<I, P>
+where
+    I: Iterator<Item = csv::Result<StringRecord>>,
+    P: RecordParser,
+{
+    type Item = Result<P::Output, Box<Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let record = match self.records.next()? {
+                Ok(record) => record,
+                Err(err) => return Some(Err(Box::new(err))),
+            };
+            match self.parser.parse(&record) {
+                Ok(Some(value)) => return Some(Ok(value)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+pub struct HeaderParser<'a, T> {
+    headers: &'a HeaderContainer,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T: FromStringRecord> RecordParser for HeaderParser<'a, T> {
+    type Output = T;
+
+    fn parse(&self, record: &StringRecord) -> Result<Option<T>, Box<Error>> {
+        T::from_string_record(self.headers, record)
+    }
+}
+
 pub trait FromStringRecord: Sized {
     fn field_count() -> usize;
     fn correct_num_headers(headers: &HeaderContainer) -> bool {
         Self::field_count() == headers.header_count()
     }
+    fn from_string_record(
+        headers: &HeaderContainer,
+        record: &StringRecord,
+    ) -> Result<Option<Self>, Box<Error>>;
+    fn stream<'a, I>(
+        headers: &'a HeaderContainer,
+        records: I,
+    ) -> Result<RecordIter<I, HeaderParser<'a, Self>>, Box<Error>>
+    where
+        I: Iterator<Item = csv::Result<StringRecord>>,
+    {
+        if !Self::correct_num_headers(headers) {
+            return Err(Box::new(IncorrectColumnsErr::new(headers.clone())));
+        }
+        let parser = HeaderParser {
+            headers,
+            _marker: PhantomData,
+        };
+        Ok(RecordIter::new(records, parser))
+    }
     fn from_string_records<T: Read>(
         headers: &HeaderContainer,
         records: &mut StringRecordsIter<T>,
-    ) -> Result<Vec<Self>, Box<Error>>;
+    ) -> Result<Vec<Self>, Box<Error>> {
+        Self::stream(headers, records)?.collect()
+    }
 }
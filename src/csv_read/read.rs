@@ -1,235 +1,551 @@
-use csv::{Reader, StringRecordsIter};
-use csv_read::errors::{IncorrectColumnsErr};
-use csv_read::{FromStringRecord, HeaderContainer};
+use csv::{Reader, StringRecord, StringRecordsIntoIter, StringRecordsIter};
+use csv_read::errors::{DateErrKind, DateParseErr, IncorrectColumnsErr};
+use csv_read::schema::{Field, Schema};
+use csv_read::{header_contains, HeaderContainer, RecordIter, RecordParser};
+use ingest::IngestFilter;
+use report::RejectReason;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs::File;
 use std::io::Read;
+use std::num::ParseIntError;
 use std::path::Path;
 use std::str::FromStr;
-use std::num::ParseIntError;
 
+pub fn is_leap_year(year: u32) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+// Month has to be between 1 - 12 inclusive
+pub fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 => {
+            if is_leap_year(year) {
+                29
+            } else {
+                28
+            }
+        }
+        _ => 31,
+    }
+}
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Hemisphere {
+    Northern,
+    Southern,
+}
+
+impl Hemisphere {
+    pub fn of_latitude(latitude: f32) -> Self {
+        if latitude < 0.0 {
+            Hemisphere::Southern
+        } else {
+            Hemisphere::Northern
+        }
+    }
+}
+
+// Meteorological seasons, eg. winter is December - February in the northern hemisphere
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    // Month has to be between 1 - 12 inclusive
+    pub fn of_month(month: u32, hemisphere: Hemisphere) -> Self {
+        let northern = match month {
+            3..=5 => Season::Spring,
+            6..=8 => Season::Summer,
+            9..=11 => Season::Autumn,
+            _ => Season::Winter,
+        };
+        match hemisphere {
+            Hemisphere::Northern => northern,
+            Hemisphere::Southern => match northern {
+                Season::Spring => Season::Autumn,
+                Season::Summer => Season::Winter,
+                Season::Autumn => Season::Spring,
+                Season::Winter => Season::Summer,
+            },
+        }
+    }
+}
+
+// Always holds a valid month, and a valid day for that month if it has one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Date {
-    pub year: Option<u32>,
-    pub month: Option<u32>,
+    pub year: u32,
+    pub month: u32,
     pub day: Option<u32>,
 }
 
+impl Date {
+    pub fn new(year: u32, month: u32, day: Option<u32>) -> Result<Self, DateErrKind> {
+        if !(1..=12).contains(&month) {
+            return Err(DateErrKind::Month(month));
+        }
+        if let Some(day) = day {
+            if !(1..=days_in_month(year, month)).contains(&day) {
+                return Err(DateErrKind::Day(day));
+            }
+        }
+        Ok(Self { year, month, day })
+    }
+
+    pub fn days_in_month(&self) -> u32 {
+        days_in_month(self.year, self.month)
+    }
+
+    // Day of the year starting at 1, the first of the month is used if there is no day
+    pub fn day_of_year(&self) -> u32 {
+        let previous_months: u32 = (1..self.month)
+            .map(|month| days_in_month(self.year, month))
+            .sum();
+        previous_months + self.day.unwrap_or(1)
+    }
+
+    pub fn season(&self, hemisphere: Hemisphere) -> Season {
+        Season::of_month(self.month, hemisphere)
+    }
+}
+
+fn parse_digits(s: &str, min_len: usize, max_len: usize) -> Option<u32> {
+    if s.len() < min_len || s.len() > max_len || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+// Accepts HH:MM, HH:MM:SS and fractional seconds, followed by an optional Z or +HH:MM offset
+fn valid_time(s: &str) -> bool {
+    let end = s.find(['Z', '+', '-']).unwrap_or(s.len());
+    let mut parts = s[..end].split(':');
+    let hour = parts.next().and_then(|hour| parse_digits(hour, 2, 2));
+    let minute = parts.next().and_then(|minute| parse_digits(minute, 2, 2));
+    let second_valid = match parts.next() {
+        Some(second) => {
+            let mut split = second.splitn(2, '.');
+            let whole = split.next().unwrap_or("");
+            let fraction_valid = split.next().is_none_or(|fraction| {
+                !fraction.is_empty() && fraction.chars().all(|c| c.is_ascii_digit())
+            });
+            fraction_valid && parse_digits(whole, 2, 2).is_some_and(|second| second <= 60)
+        }
+        None => true,
+    };
+    match (hour, minute) {
+        (Some(hour), Some(minute)) => {
+            hour < 24
+                && minute < 60
+                && second_valid
+                && parts.next().is_none()
+                && valid_offset(&s[end..])
+        }
+        _ => false,
+    }
+}
+
+// Nothing, a lone Z, or +HH:MM / -HH:MM
+fn valid_offset(s: &str) -> bool {
+    if s.is_empty() || s == "Z" {
+        return true;
+    }
+    if !s.starts_with(['+', '-']) {
+        return false;
+    }
+    let mut parts = s[1..].split(':');
+    let hour = parts.next().and_then(|hour| parse_digits(hour, 2, 2));
+    let minute = parts.next().and_then(|minute| parse_digits(minute, 2, 2));
+    match (hour, minute) {
+        (Some(hour), Some(minute)) => hour < 24 && minute < 60 && parts.next().is_none(),
+        _ => false,
+    }
+}
+
 impl FromStr for Date {
-    type Err = ParseIntError;
-    
-    // Has to be in form year-month-day
+    type Err = DateParseErr;
+
+    // Has to be in form YYYY-MM, YYYY-MM-DD or an ISO timestamp YYYY-MM-DDTHH:MM:SS
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut split = s.split('-');
-        let year = split.next().and_then(|x| x.parse().ok());
-        let month = split.next().and_then(|x| x.parse().ok());
-        let day = split.next().and_then(|x| x.parse().ok());
+        let input = s.trim();
+        let err = |kind| DateParseErr::new(input, kind);
+        let (date, time) = match input.find(['T', ' ']) {
+            Some(split) => (&input[..split], Some(&input[split + 1..])),
+            None => (input, None),
+        };
+
+        let parts: Vec<&str> = date.split('-').collect();
+        let year = parse_digits(parts[0], 4, 4).ok_or_else(|| err(DateErrKind::Format))?;
+        let month = match parts.get(1) {
+            Some(month) => parse_digits(month, 1, 2).ok_or_else(|| err(DateErrKind::Format))?,
+            None => return Err(err(DateErrKind::Format)),
+        };
+        let day = match parts.get(2) {
+            Some(day) => Some(parse_digits(day, 1, 2).ok_or_else(|| err(DateErrKind::Format))?),
+            None => None,
+        };
+        if parts.len() > 3 {
+            return Err(err(DateErrKind::Format));
+        }
+        if let Some(time) = time {
+            if day.is_none() {
+                return Err(err(DateErrKind::Format));
+            }
+            if !valid_time(time) {
+                return Err(err(DateErrKind::Time));
+            }
+        }
 
-        return Ok(Self {
-            year,
-            month,
-            day
-        });
+        Date::new(year, month, day).map_err(err)
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
-pub struct TempStation {
-    date: Option<Date>,
-    longitude: Option<f32>,
-    latitude: Option<f32>,
-    elevation: Option<f32>,
-    avg_temp: Option<f32>,
+// Parsed from the "<ELEMENT>_ATTRIBUTES" columns of GSOM files. The number of comma separated
+// fields depends on the element:
+//   4 fields = missing days, measurement flag, quality flag, source flag (eg. TMAX, PRCP)
+//   3 fields = measurement flag, quality flag, source flag
+//   2 fields = missing days, source flag (eg. TAVG)
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub struct ElementAttributes {
+    pub missing_days: Option<u32>,
+    pub measurement: Option<char>,
+    pub quality: Option<char>,
+    pub source: Option<char>,
 }
 
-impl FromStringRecord for TempStation {
-    fn field_count() -> usize {
-        5
+impl ElementAttributes {
+    pub fn attributes_column(element: &str) -> String {
+        format!("{}_ATTRIBUTES", element)
     }
-    fn from_string_records<T: Read>(
-        headers: &HeaderContainer,
-        records: &mut StringRecordsIter<T>,
-    ) -> Result<Vec<Self>, Box<Error>> {
-        if Self::correct_num_headers(headers) {
-            let long_col = headers.column_with_name("LONGITUDE")?;
-            let lat_col = headers.column_with_name("LATITUDE")?;
-            let ele_col = headers.column_with_name("ELEVATION")?;
-            let temp_col = headers.column_with_name("TAVG")?;
-            let date_col = headers.column_with_name("DATE")?;
-            
-
-            let mut values = Vec::new();
-            for record in records {
-                let record = record?;
-                let mut long = None;
-                let mut lat = None;
-                let mut ele = None;
-                let mut temp = None;
-                let mut date = None;
-                if let Ok(num) = record[long_col].parse::<f32>() {
-                    long = Some(num);
-                }
-                if let Ok(num) = record[lat_col].parse::<f32>() {
-                    lat = Some(num);
-                }
-                if let Ok(num) = record[ele_col].parse::<f32>() {
-                    ele = Some(num);
-                }
-                if let Ok(num) = record[temp_col].parse::<f32>() {
-                    temp = Some(num);
-                }
-                if let Ok(num) = record[date_col].parse::<Date>() {
-                    date = Some(num);
-                }
-                if temp != None {
-                    values.push(Self {
-                        date,
-                        longitude: long,
-                        latitude: lat,
-                        elevation: ele,
-                        avg_temp: temp,
-                    });
-                }
+}
+
+fn parse_flag(field: &str) -> Option<char> {
+    field.trim().chars().next()
+}
+
+impl FromStr for ElementAttributes {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(',').collect();
+        let missing_days = |field: &str| -> Result<Option<u32>, ParseIntError> {
+            let field = field.trim();
+            if field.is_empty() {
+                Ok(None)
+            } else {
+                field.parse().map(Some)
             }
-            return Ok(values);
-        }
-        Err(Box::new(IncorrectColumnsErr::new(headers.clone())))
+        };
+
+        let attributes = match fields.len() {
+            4 => Self {
+                missing_days: missing_days(fields[0])?,
+                measurement: parse_flag(fields[1]),
+                quality: parse_flag(fields[2]),
+                source: parse_flag(fields[3]),
+            },
+            3 => Self {
+                missing_days: None,
+                measurement: parse_flag(fields[0]),
+                quality: parse_flag(fields[1]),
+                source: parse_flag(fields[2]),
+            },
+            2 => Self {
+                missing_days: missing_days(fields[0])?,
+                source: parse_flag(fields[1]),
+                ..Default::default()
+            },
+            _ => Self {
+                source: parse_flag(fields[0]),
+                ..Default::default()
+            },
+        };
+        Ok(attributes)
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
-pub struct WindStation {
+// Columns every GSOM station file is expected to have, element columns are appended to these
+pub const STATION_COLUMNS: [&str; 3] = ["DATE", "LONGITUDE", "LATITUDE"];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StationRecord {
+    pub station: Option<String>,
+    pub name: Option<String>,
     pub date: Option<Date>,
     pub longitude: Option<f32>,
     pub latitude: Option<f32>,
-    pub avg_wind: Option<f32>,
+    pub elevation: Option<f32>,
+    pub values: HashMap<String, f32>,
+    pub attributes: HashMap<String, ElementAttributes>,
+    // Elements with a value that couldn't be parsed
+    #[serde(default)]
+    pub unparsable: Vec<String>,    // Elements with an attributes cell that couldn't be parsed, so their flags are unknown
+    #[serde(default)]
+    pub unparsable_attributes: Vec<String>,
 }
 
-impl FromStringRecord for WindStation {
-    fn field_count() -> usize {
-        4
+impl StationRecord {
+    pub fn value(&self, element: &str) -> Option<f32> {
+        self.values.get(element).cloned()
+    }
+
+    pub fn attributes(&self, element: &str) -> Option<ElementAttributes> {
+        self.attributes.get(element).cloned()
     }
 
-    fn from_string_records<T: Read>(
+    // Removes every value for which the predicate returns false
+    pub fn retain_values<F>(&mut self, func: F)
+    where
+        F: Fn(&str, Option<ElementAttributes>) -> bool,
+    {
+        let attributes = &self.attributes;
+        self.values
+            .retain(|element, _| func(element, attributes.get(element).cloned()));
+    }
+
+    // Reads every record that has a value for at least one of the given elements, rejected
+    // rows are left out
+    pub fn from_string_records<T: Read>(
         headers: &HeaderContainer,
+        elements: &[&str],
+        optional_cols: &OptionalColumns,
         records: &mut StringRecordsIter<T>,
     ) -> Result<Vec<Self>, Box<Error>> {
-        if Self::correct_num_headers(headers) {
-            let long_col = headers.column_with_name("LONGITUDE")?;
-            let lat_col = headers.column_with_name("LATITUDE")?;
-            let date_col = headers.column_with_name("DATE")?;
-            let avg_wind_col = headers.column_with_name("AWND")?;
-            
-
-            let mut values = Vec::new();
-            for record in records {
-                let record = record?;
-                let mut long = record[long_col].parse().ok();
-                let mut lat = record[lat_col].parse().ok();
-                let mut date = record[date_col].parse().ok();
-                let mut avg_wind = record[avg_wind_col].parse().ok();
-                if avg_wind != None {
-                    values.push(Self {
-                        date,
-                        longitude: long,
-                        latitude: lat,
-                        avg_wind
-                    });
-                }
+        let columns = StationColumns::new(headers, elements, optional_cols.clone())?;
+        let mut stations = Vec::new();
+        for row in RecordIter::new(records, columns) {
+            if let Ok(station) = row? {
+                stations.push(station);
             }
-            return Ok(values);
         }
-        Err(Box::new(IncorrectColumnsErr::new(headers.clone())))
+        Ok(stations)
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
-pub struct RainStation {
-    pub date: Option<Date>,
-    pub longitude: Option<f32>,
-    pub latitude: Option<f32>,
-    pub precip: Option<f32>,
+// Resolved columns of a station file, used to parse each record into a StationRecord
+#[derive(Debug, Clone)]
+pub struct StationColumns {
+    date: usize,
+    longitude: usize,
+    latitude: usize,
+    // Element name, value column and attributes column
+    elements: Vec<(String, usize, Option<usize>)>,
+    optional: OptionalColumns,
+    filter: Option<IngestFilter>,
 }
 
-impl FromStringRecord for RainStation {
-    fn field_count() -> usize {
-        4
+impl StationColumns {
+    pub fn new(
+        headers: &HeaderContainer,
+        elements: &[&str],
+        optional: OptionalColumns,
+    ) -> Result<Self, Box<Error>> {
+        if headers.header_count() != STATION_COLUMNS.len() + elements.len() {
+            return Err(Box::new(IncorrectColumnsErr::new(headers.clone())));
+        }
+        let mut element_cols = Vec::with_capacity(elements.len());
+        for (i, &element) in elements.iter().enumerate() {
+            let attributes_col = optional.attributes.get(i).and_then(|&col| col);
+            let col = headers.column_with_name(element)?;
+            element_cols.push((String::from(element), col, attributes_col));
+        }
+        Ok(Self {
+            date: headers.column_with_name("DATE")?,
+            longitude: headers.column_with_name("LONGITUDE")?,
+            latitude: headers.column_with_name("LATITUDE")?,
+            elements: element_cols,
+            optional,
+            filter: None,
+        })
     }
 
-    fn from_string_records<T: Read>(
-        headers: &HeaderContainer,
-        records: &mut StringRecordsIter<T>,
-    ) -> Result<Vec<Self>, Box<Error>> {
-        if Self::correct_num_headers(headers) {
-            let long_col = headers.column_with_name("LONGITUDE")?;
-            let lat_col = headers.column_with_name("LATITUDE")?;
-            let date_col = headers.column_with_name("DATE")?;
-            let precip_col = headers.column_with_name("PRCP")?;
-            
-
-            let mut values = Vec::new();
-            for record in records {
-                let record = record?;
-                let mut long = record[long_col].parse().ok();
-                let mut lat = record[lat_col].parse().ok();
-                let mut date = record[date_col].parse().ok();
-                let mut precip = record[precip_col].parse().ok();
-                if precip != None {
-                    values.push(Self {
-                        date,
-                        longitude: long,
-                        latitude: lat,
-                        precip
-                    });
+    // Records the filter rejects are returned as rejected rows
+    pub fn with_filter(mut self, filter: IngestFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+}
+
+// A row of a station file that can't be used, with its fields for the report
+#[derive(Debug, Clone)]
+pub struct RejectedRow {
+    pub reason: RejectReason,
+    pub record: StringRecord,
+}
+
+pub type StationRow = Result<StationRecord, RejectedRow>;
+
+impl RecordParser for StationColumns {
+    type Output = StationRow;
+
+    // Rows without a date or without a value for any of the elements are rejected rather than
+    // skipped, so they can be counted
+    fn parse(&self, record: &StringRecord) -> Result<Option<StationRow>, Box<Error>> {
+        let reject = |reason| {
+            Ok(Some(Err(RejectedRow {
+                reason,
+                record: record.clone(),
+            })))
+        };
+        let date = match record[self.date].trim() {
+            "" => return reject(RejectReason::MissingDate),
+            field => match field.parse() {
+                Ok(date) => date,
+                Err(_) => return reject(RejectReason::InvalidDate),
+            },
+        };
+        let mut element_values = HashMap::with_capacity(self.elements.len());
+        let mut element_attributes = HashMap::new();
+        let mut unparsable = Vec::new();
+        let mut unparsable_attributes = Vec::new();
+        for &(ref element, col, attributes_col) in &self.elements {
+            let field = record[col].trim();
+            if field.is_empty() {
+                continue;
+            }
+            match field.parse::<f32>() {
+                Ok(num) => {
+                    element_values.insert(element.clone(), num);
+                    if let Some(col) = attributes_col {
+                        match record[col].parse() {
+                            Ok(attributes) => {
+                                element_attributes.insert(element.clone(), attributes);
+                            }
+                            Err(_) => unparsable_attributes.push(element.clone()),
+                        }
+                    }
                 }
+                Err(_) => unparsable.push(element.clone()),
             }
-            return Ok(values);
         }
-        Err(Box::new(IncorrectColumnsErr::new(headers.clone())))
+        if element_values.is_empty() {
+            return reject(if unparsable.is_empty() {
+                RejectReason::MissingValue
+            } else {
+                RejectReason::UnparsableValue
+            });
+        }
+        let optional = &self.optional;
+        let station = StationRecord {
+            station: optional.text(record, optional.station),
+            name: optional.text(record, optional.name),
+            date: Some(date),
+            longitude: record[self.longitude].parse().ok(),
+            latitude: record[self.latitude].parse().ok(),
+            elevation: optional.elevation.and_then(|col| record[col].parse().ok()),
+            values: element_values,
+            attributes: element_attributes,
+            unparsable,
+            unparsable_attributes,
+        };
+        if let Some(ref filter) = self.filter {
+            if let Err(reason) = filter.check_record(&station) {
+                return reject(reason);
+            }
+        }
+        Ok(Some(Ok(station)))
     }
 }
 
-pub fn get_temp_stations<P: AsRef<Path>>(path: P) -> Result<Vec<TempStation>, Box<Error>> {
-    let mut reader = Reader::from_path(path)?;
-    let headers;
-    {
-        let header_record = reader.headers()?;
-        headers = HeaderContainer::from_record(
-            header_record,
-            &["DATE", "LONGITUDE", "LATITUDE", "ELEVATION", "TAVG"],
-            &mut vec![],
-        )?;
+// Columns that are read when present but are not required
+#[derive(Debug, Clone, Default)]
+pub struct OptionalColumns {
+    pub station: Option<usize>,
+    pub name: Option<usize>,
+    pub elevation: Option<usize>,
+    // Attribute column of each requested element, in the same order as the elements
+    pub attributes: Vec<Option<usize>>,
+}
+
+impl OptionalColumns {
+    pub fn from_record(headers: &StringRecord, elements: &[&str]) -> Self {
+        Self {
+            station: header_contains(headers, "STATION"),
+            name: header_contains(headers, "NAME"),
+            elevation: header_contains(headers, "ELEVATION"),
+            attributes: elements
+                .iter()
+                .map(|element| {
+                    header_contains(headers, &ElementAttributes::attributes_column(element))
+                })
+                .collect(),
+        }
+    }
+
+    fn text(&self, record: &StringRecord, col: Option<usize>) -> Option<String> {
+        let field = record.get(col?)?.trim();
+        if field.is_empty() {
+            return None;
+        }
+        Some(String::from(field))
+    }
+}
+
+pub type StationIter = RecordIter<StringRecordsIntoIter<File>, StationColumns>;
+
+impl<I> RecordIter<I, StationColumns> {
+    // Eg. stream_stations(path, &["TAVG"])?.with_filter(IngestFilter::new().with_years(1981, 2010))
+    pub fn with_filter(mut self, filter: IngestFilter) -> Self {
+        self.parser = self.parser.with_filter(filter);
+        self
     }
-    let values = TempStation::from_string_records(&headers, &mut reader.records())?;
-    Ok(values)
 }
 
-pub fn get_wind_stations<P: AsRef<Path>>(path: P) -> Result<Vec<WindStation>, Box<Error>> {
+// Lazily reads the given GSOM element columns (eg. "TAVG", "TMAX", "PRCP") from a station
+// file. Fails if the file is missing any of the elements
+pub fn stream_stations<P: AsRef<Path>>(
+    path: P,
+    elements: &[&str],
+) -> Result<StationIter, Box<Error>> {
     let mut reader = Reader::from_path(path)?;
-    let headers;
+    let columns;
     {
         let header_record = reader.headers()?;
-        headers = HeaderContainer::from_record(
-            header_record,
-            &["DATE", "LONGITUDE", "LATITUDE", "AWND"],
-            &mut vec![],
-        )?;
+        let mut names = STATION_COLUMNS.to_vec();
+        names.extend_from_slice(elements);
+        let headers = HeaderContainer::from_record(header_record, &names, &mut vec![])?;
+        let optional_cols = OptionalColumns::from_record(header_record, elements);
+        columns = StationColumns::new(&headers, elements, optional_cols)?;
     }
-    let values = WindStation::from_string_records(&headers, &mut reader.records())?;
-    Ok(values)
+    Ok(RecordIter::new(reader.into_records(), columns))
 }
 
-pub fn get_precip_stations<P: AsRef<Path>>(path: P) -> Result<Vec<RainStation>, Box<Error>> {
+// Lazily reads the schema's value from a csv whose columns are found through the schema's
+// aliases. The value is stored under the schema's element
+pub fn stream_schema_stations<P: AsRef<Path>>(
+    path: P,
+    schema: &Schema,
+) -> Result<StationIter, Box<Error>> {
     let mut reader = Reader::from_path(path)?;
-    let headers;
+    let columns;
     {
         let header_record = reader.headers()?;
-        headers = HeaderContainer::from_record(
-            header_record,
-            &["DATE", "LONGITUDE", "LATITUDE", "PRCP"],
-            &mut vec![],
-        )?;
-    }
-    let values = RainStation::from_string_records(&headers, &mut reader.records())?;
-    Ok(values)
-}
\ No newline at end of file
+        let elements = [schema.element.as_str()];
+        let headers = schema.resolve(header_record)?;
+        let mut optional_cols = OptionalColumns::from_record(header_record, &elements);
+        optional_cols.elevation = schema.optional_column(header_record, Field::Elevation);
+        columns = StationColumns::new(&headers, &elements, optional_cols)?;
+    }
+    Ok(RecordIter::new(reader.into_records(), columns))
+}
+
+// Reads the given GSOM element columns (eg. "TAVG", "TMAX", "PRCP") from a station file.
+// Fails if the file is missing any of the elements, rejected rows are left out
+pub fn get_stations<P: AsRef<Path>>(
+    path: P,
+    elements: &[&str],
+) -> Result<Vec<StationRecord>, Box<Error>> {
+    let mut stations = Vec::new();
+    for row in stream_stations(path, elements)? {
+        if let Ok(station) = row? {
+            stations.push(station);
+        }
+    }
+    Ok(stations)
+}
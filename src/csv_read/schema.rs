@@ -0,0 +1,224 @@
+use csv::{Reader, StringRecord};
+use csv_read::errors::{HeaderContainerErr, SchemaErr};
+use csv_read::{header_contains_alias, Header, HeaderContainer};
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use units::Unit;
+
+// The logical columns a data source has to provide
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Field {
+    Longitude,
+    Latitude,
+    Elevation,
+    Date,
+    Value,
+}
+
+impl Field {
+    // Name the resolved column is given in the HeaderContainer. The value column is named
+    // after the schema's element instead
+    pub fn header_name(&self) -> &'static str {
+        match self {
+            Field::Longitude => "LONGITUDE",
+            Field::Latitude => "LATITUDE",
+            Field::Elevation => "ELEVATION",
+            Field::Date => "DATE",
+            Field::Value => "VALUE",
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = SchemaErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "longitude" => Ok(Field::Longitude),
+            "latitude" => Ok(Field::Latitude),
+            "elevation" => Ok(Field::Elevation),
+            "date" => Ok(Field::Date),
+            "value" => Ok(Field::Value),
+            _ => Err(SchemaErr::new(format!("Unknown field \"{}\"", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FieldSpec {
+    pub field: Field,
+    // Header names accepted for the field, matched case-insensitively
+    pub aliases: Vec<String>,
+    pub unit: Option<Unit>,
+    // Missing optional fields don't stop a file being read
+    pub required: bool,
+}
+
+// Row of a schema config file
+#[derive(Debug, Deserialize)]
+struct SchemaRow {
+    #[serde(rename = "FIELD")]
+    field: String,
+    // Aliases separated by '|'
+    #[serde(rename = "ALIASES")]
+    aliases: String,
+    #[serde(rename = "UNIT")]
+    unit: Option<String>,
+}
+
+// Describes how the columns of a csv map onto the fields the crate needs. Eg.
+//   FIELD,ALIASES,UNIT
+//   longitude,lon|lng|Longitude_deg,
+//   latitude,lat,
+//   date,date|time,
+//   value,temp_c,degC
+// Elevation is optional, every other field has to be listed
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Schema {
+    // Name the value is stored under, eg. StationRecord::value(element)
+    pub element: String,
+    fields: Vec<FieldSpec>,
+}
+
+impl Schema {
+    pub fn new(element: &str) -> Self {
+        Self {
+            element: String::from(element),
+            fields: Vec::new(),
+        }
+    }
+
+    // Schema of the GSOM station files for the given element code, assuming metric units
+    pub fn gsom(element: &str) -> Self {
+        let mut schema = Self::new(element)
+            .with_field(Field::Longitude, &["LONGITUDE"])
+            .with_field(Field::Latitude, &["LATITUDE"])
+            .with_field(Field::Date, &["DATE"])
+            .with_field(Field::Value, &[element])
+            .with_optional_field(Field::Elevation, &["ELEVATION"])
+            .with_unit(Field::Elevation, Unit::Metre);
+        if let Some(unit) = Unit::gsom_metric(element) {
+            schema = schema.with_unit(Field::Value, unit);
+        }
+        schema
+    }
+
+    pub fn with_element(mut self, element: &str) -> Self {
+        self.element = String::from(element);
+        self
+    }
+
+    // Replaces any previous spec for the field
+    pub fn with_field(self, field: Field, aliases: &[&str]) -> Self {
+        self.with_spec(field, aliases, true)
+    }
+
+    pub fn with_optional_field(self, field: Field, aliases: &[&str]) -> Self {
+        self.with_spec(field, aliases, false)
+    }
+
+    fn with_spec(mut self, field: Field, aliases: &[&str], required: bool) -> Self {
+        self.fields.retain(|spec| spec.field != field);
+        self.fields.push(FieldSpec {
+            field,
+            aliases: aliases.iter().map(|&alias| String::from(alias)).collect(),
+            unit: None,
+            required,
+        });
+        self
+    }
+
+    pub fn with_unit(mut self, field: Field, unit: Unit) -> Self {
+        for spec in self.fields.iter_mut().filter(|spec| spec.field == field) {
+            spec.unit = Some(unit);
+        }
+        self
+    }
+
+    pub fn spec(&self, field: Field) -> Option<&FieldSpec> {
+        self.fields.iter().find(|spec| spec.field == field)
+    }
+
+    pub fn unit(&self, field: Field) -> Option<Unit> {
+        self.spec(field).and_then(|spec| spec.unit)
+    }
+
+    pub fn header_name(&self, field: Field) -> &str {
+        match field {
+            Field::Value => &self.element,
+            _ => field.header_name(),
+        }
+    }
+
+    // Column of an optional field if the csv has it
+    pub fn optional_column(&self, headers: &StringRecord, field: Field) -> Option<usize> {
+        header_contains_alias(headers, &self.spec(field)?.aliases)
+    }
+
+    // Finds the column of every required field, naming each with Schema::header_name. Fails with
+    // a HeaderContainerErr if a field has no column, or a SchemaErr if two fields share one
+    pub fn resolve(&self, headers: &StringRecord) -> Result<HeaderContainer, Box<Error>> {
+        let mut container = HeaderContainer::with_capacity(self.fields.len());
+        let mut missing = Vec::new();
+        for spec in self.fields.iter().filter(|spec| spec.required) {
+            match header_contains_alias(headers, &spec.aliases) {
+                Some(column) => {
+                    let header = Header::new(String::from(self.header_name(spec.field)), column);
+                    if container.add_header(header).is_err() {
+                        return Err(Box::new(SchemaErr::new(format!(
+                            "Field \"{}\" resolves to the same column \"{}\" as another field",
+                            self.header_name(spec.field),
+                            &headers[column]
+                        ))));
+                    }
+                }
+                None => missing.push(spec.aliases.join("|")),
+            }
+        }
+        if missing.is_empty() {
+            Ok(container)
+        } else {
+            Err(Box::new(HeaderContainerErr::new(missing, headers.clone())))
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let mut reader = Reader::from_path(path)?;
+        let mut schema = Self::new(Field::Value.header_name());
+        for result in reader.deserialize() {
+            let row: SchemaRow = result?;
+            let field: Field = row.field.parse()?;
+            let aliases: Vec<&str> = row
+                .aliases
+                .split('|')
+                .map(|alias| alias.trim())
+                .filter(|alias| !alias.is_empty())
+                .collect();
+            if aliases.is_empty() {
+                return Err(Box::new(SchemaErr::new(format!(
+                    "Field \"{}\" has no aliases",
+                    row.field
+                ))));
+            }
+            schema = match field {
+                Field::Elevation => schema.with_optional_field(field, &aliases),
+                _ => schema.with_field(field, &aliases),
+            };
+            if let Some(unit) = row.unit.as_ref().map(|unit| unit.trim()) {
+                if !unit.is_empty() {
+                    schema = schema.with_unit(field, unit.parse()?);
+                }
+            }
+        }
+        for &field in &[Field::Longitude, Field::Latitude, Field::Date, Field::Value] {
+            if schema.spec(field).is_none() {
+                return Err(Box::new(SchemaErr::new(format!(
+                    "Schema is missing the {} field",
+                    field.header_name().to_lowercase()
+                ))));
+            }
+        }
+        Ok(schema)
+    }
+}
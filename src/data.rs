@@ -1,5 +1,6 @@
-use csv_read::read::{RainStation, WindStation};
+use csv_read::read::StationRecord;
 use math::Point;
+use report::RejectReason;
 use std::ops::{Add, AddAssign, Div};
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
@@ -171,6 +172,9 @@ impl YearlyData<f32> {
 
 #[derive(Debug, Deserialize)]
 pub struct CsvRecord {
+    // Older files written without a STATION column read as None
+    #[serde(rename = "STATION", default)]
+    pub station: Option<String>,
     #[serde(rename = "YEAR")]
     pub year: Option<u32>,
     #[serde(rename = "MONTH")]
@@ -189,38 +193,61 @@ pub struct CsvRecord {
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub struct TemperaturePoint {
+    pub year: Option<u32>,
     pub month: usize,
     pub data: DataPoint<f32>,
+    // Index into the StationTable saved next to the point cache
+    pub station: Option<u32>,
 }
 
 impl TemperaturePoint {
     pub fn new(month: usize, data: DataPoint<f32>) -> Self {
-        Self { month, data }
+        Self {
+            year: None,
+            month,
+            data,
+            station: None,
+        }
     }
-    pub fn from(record: CsvRecord) -> Option<Self> {
-        let temp_avg = record.avg_temp;
-        Some(Self {
-            month: record.month? as usize,
-            data: DataPoint::new(Point::new(record.longitude?, record.latitude?), temp_avg),
-        })
+
+    pub fn with_year(mut self, year: u32) -> Self {
+        self.year = Some(year);
+        self
     }
-    pub fn from_wind(station: WindStation) -> Option<Self> {
-        Some(Self {
-            month: station.date?.month? as usize,
-            data: DataPoint::new(
-                Point::new(station.longitude?, station.latitude?),
-                station.avg_wind?,
-            ),
+
+    pub fn with_station(mut self, station: u32) -> Self {
+        self.station = Some(station);
+        self
+    }
+    pub fn from(record: &CsvRecord) -> Result<Self, RejectReason> {
+        let month = record.month.ok_or(RejectReason::MissingDate)?;
+        if !(1..=12).contains(&month) {
+            return Err(RejectReason::InvalidDate);
+        }
+        let position = match (record.longitude, record.latitude) {
+            (Some(long), Some(lat)) => Point::new(long, lat),
+            _ => return Err(RejectReason::MissingCoordinate),
+        };
+        let point = Self::new(month as usize, DataPoint::new(position, record.avg_temp));
+        Ok(match record.year {
+            Some(year) => point.with_year(year),
+            None => point,
         })
     }
 
-    pub fn from_rain(station: RainStation) -> Option<Self> {
-        Some(Self {
-            month: station.date?.month? as usize,
-            data: DataPoint::new(
-                Point::new(station.longitude?, station.latitude?),
-                station.precip?,
-            ),
-        })
+    pub fn from_station(station: &StationRecord, element: &str) -> Result<Self, RejectReason> {
+        let date = station.date.ok_or(RejectReason::MissingDate)?;
+        let position = match (station.longitude, station.latitude) {
+            (Some(long), Some(lat)) => Point::new(long, lat),
+            _ => return Err(RejectReason::MissingCoordinate),
+        };
+        let value = match station.value(element) {
+            Some(value) => value,
+            None if station.unparsable.iter().any(|name| name == element) => {
+                return Err(RejectReason::UnparsableValue)
+            }
+            None => return Err(RejectReason::MissingValue),
+        };
+        Ok(Self::new(date.month as usize, DataPoint::new(position, value)).with_year(date.year))
     }
 }
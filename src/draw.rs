@@ -0,0 +1,61 @@
+use colormap::Rgb;
+use image::{Rgba, RgbaImage};
+
+// Drawing on rendered images, shared by the legend and the map overlays
+
+pub fn to_rgba(colour: Rgb) -> Rgba<u8> {
+    Rgba {
+        data: [
+            (colour[0].clamp(0.0, 1.0) * 255.0).round() as u8,
+            (colour[1].clamp(0.0, 1.0) * 255.0).round() as u8,
+            (colour[2].clamp(0.0, 1.0) * 255.0).round() as u8,
+            255,
+        ],
+    }
+}
+
+// Draws the colour over the pixel with its alpha scaled by coverage. Works on transparent
+// images too, so overlays can be drawn on their own and put over a map later
+pub fn blend_pixel(image: &mut RgbaImage, x: i64, y: i64, colour: Rgba<u8>, coverage: f32) {
+    if x < 0 || y < 0 || x >= i64::from(image.width()) || y >= i64::from(image.height()) {
+        return;
+    }
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    let alpha = f32::from(colour.data[3]) / 255.0 * coverage.clamp(0.0, 1.0);
+    let below = f32::from(pixel.data[3]) / 255.0 * (1.0 - alpha);
+    let total = alpha + below;
+    if total <= 0.0 {
+        return;
+    }
+    for channel in 0..3 {
+        let value = (f32::from(colour.data[channel]) * alpha
+            + f32::from(pixel.data[channel]) * below)
+            / total;
+        pixel.data[channel] = value.round() as u8;
+    }
+    pixel.data[3] = (total * 255.0).round() as u8;
+}
+
+pub fn fill_rect(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, colour: Rgba<u8>) {
+    let right = (x + width).min(image.width());
+    let bottom = (y + height).min(image.height());
+    for py in y..bottom {
+        for px in x..right {
+            image.put_pixel(px, py, colour);
+        }
+    }
+}
+
+pub fn outline_rect(
+    image: &mut RgbaImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    colour: Rgba<u8>,
+) {
+    fill_rect(image, x, y, width, 1, colour);
+    fill_rect(image, x, y + height - 1, width, 1, colour);
+    fill_rect(image, x, y, 1, height, colour);
+    fill_rect(image, x + width - 1, y, 1, height, colour);
+}
@@ -0,0 +1,572 @@
+use grid::Grid;
+use heatmap::HeatMap;
+use math::{Range, RangeBox};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use units::Unit;
+
+// Written for None cells. Cells with this value or NaN are read as None, as is the nodata value
+// of a file written elsewhere
+pub const NODATA: f32 = -9999.0;
+
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const PHOTOMETRIC_INTERPRETATION: u16 = 262;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const PLANAR_CONFIGURATION: u16 = 284;
+const TILE_WIDTH: u16 = 322;
+const SAMPLE_FORMAT: u16 = 339;
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const GEO_KEY_DIRECTORY: u16 = 34735;
+const GDAL_METADATA: u16 = 42112;
+const GDAL_NODATA: u16 = 42113;
+
+// Field types
+const BYTE: u16 = 1;
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const SSHORT: u16 = 8;
+const SLONG: u16 = 9;
+const FLOAT: u16 = 11;
+const DOUBLE: u16 = 12;
+
+// Sample formats
+const UNSIGNED_INT: u16 = 1;
+const SIGNED_INT: u16 = 2;
+const IEEE_FLOAT: u16 = 3;
+
+// GeoKeys and their values
+const GT_MODEL_TYPE: u16 = 1024;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const GT_RASTER_TYPE: u16 = 1025;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+const GEOGRAPHIC_TYPE: u16 = 2048;
+const GCS_WGS_84: u16 = 4326;
+const GEOG_ANGULAR_UNITS: u16 = 2054;
+const ANGULAR_DEGREE: u16 = 9102;
+
+// Key, location, count and value of the keys written for WGS84 latitude and longitude
+const WGS84_KEYS: [[u16; 4]; 4] = [
+    [GT_MODEL_TYPE, 0, 1, MODEL_TYPE_GEOGRAPHIC],
+    [GT_RASTER_TYPE, 0, 1, RASTER_PIXEL_IS_AREA],
+    [GEOGRAPHIC_TYPE, 0, 1, GCS_WGS_84],
+    [GEOG_ANGULAR_UNITS, 0, 1, ANGULAR_DEGREE],
+];
+
+// Directory version 1.1.0 followed by the keys
+fn geo_key_directory() -> Vec<u16> {
+    let mut directory = vec![1, 1, 0, WGS84_KEYS.len() as u16];
+    for key in WGS84_KEYS.iter() {
+        directory.extend_from_slice(key);
+    }
+    directory
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoTiffErr {
+    NotATiff,
+    MissingTag(&'static str),
+    // Valid TIFFs the reader can't handle, eg. compressed or tiled images
+    Unsupported(String),
+    // Offsets or counts that point outside the file
+    Truncated,
+}
+
+impl Display for GeoTiffErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            GeoTiffErr::NotATiff => write!(f, "File is not a TIFF"),
+            GeoTiffErr::MissingTag(tag) => write!(f, "GeoTIFF is missing the {} tag", tag),
+            GeoTiffErr::Unsupported(feature) => write!(f, "GeoTIFF uses unsupported {}", feature),
+            GeoTiffErr::Truncated => write!(f, "GeoTIFF is truncated or corrupt"),
+        }
+    }
+}
+
+impl Error for GeoTiffErr {}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl Entry {
+    fn shorts(tag: u16, values: &[u16]) -> Self {
+        let mut data = Vec::with_capacity(values.len() * 2);
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        Self {
+            tag,
+            kind: SHORT,
+            count: values.len() as u32,
+            data,
+        }
+    }
+
+    fn longs(tag: u16, values: &[u32]) -> Self {
+        let mut data = Vec::with_capacity(values.len() * 4);
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        Self {
+            tag,
+            kind: LONG,
+            count: values.len() as u32,
+            data,
+        }
+    }
+
+    fn doubles(tag: u16, values: &[f64]) -> Self {
+        let mut data = Vec::with_capacity(values.len() * 8);
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        Self {
+            tag,
+            kind: DOUBLE,
+            count: values.len() as u32,
+            data,
+        }
+    }
+
+    fn ascii(tag: u16, text: &str) -> Self {
+        let mut data = text.as_bytes().to_vec();
+        data.push(0);
+        Self {
+            tag,
+            kind: ASCII,
+            count: data.len() as u32,
+            data,
+        }
+    }
+}
+
+// Writes a little endian, uncompressed float32 GeoTIFF in WGS84 with one strip per row. Grid
+// cells are centred on range.from + index * cell size (see HeatMap::locate), so the raster's
+// edges lie half a cell outside the range
+pub fn write_geotiff(
+    path: impl AsRef<Path>,
+    grid: &Grid<Option<f32>>,
+    range: &RangeBox<f32>,
+) -> Result<(), Box<Error>> {
+    let (width, height) = (grid.horizontal, grid.vertical);
+    let row_bytes = width * 4;
+    // The image data follows the 8 byte header, rows are stored north first
+    let mut image = Vec::with_capacity(row_bytes * height);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let value = grid[[x, y]].filter(|value| !value.is_nan());
+            image.extend_from_slice(&value.unwrap_or(NODATA).to_le_bytes());
+        }
+    }
+    let strip_offsets: Vec<u32> = (0..height)
+        .map(|row| (8 + row * row_bytes) as u32)
+        .collect();
+    let strip_counts = vec![row_bytes as u32; height];
+
+    let scale_x = f64::from(range.horizontal.length()) / width as f64;
+    let scale_y = f64::from(range.vertical.length()) / height as f64;
+    let west = f64::from(range.horizontal.from) - scale_x / 2.0;
+    let north = f64::from(range.vertical.from) + (height as f64 - 0.5) * scale_y;
+
+    let mut entries = vec![
+        Entry::longs(IMAGE_WIDTH, &[width as u32]),
+        Entry::longs(IMAGE_LENGTH, &[height as u32]),
+        Entry::shorts(BITS_PER_SAMPLE, &[32]),
+        Entry::shorts(COMPRESSION, &[1]),
+        // Black is zero
+        Entry::shorts(PHOTOMETRIC_INTERPRETATION, &[1]),
+        Entry::longs(STRIP_OFFSETS, &strip_offsets),
+        Entry::shorts(SAMPLES_PER_PIXEL, &[1]),
+        Entry::longs(ROWS_PER_STRIP, &[1]),
+        Entry::longs(STRIP_BYTE_COUNTS, &strip_counts),
+        Entry::shorts(PLANAR_CONFIGURATION, &[1]),
+        Entry::shorts(SAMPLE_FORMAT, &[IEEE_FLOAT]),
+        Entry::doubles(MODEL_PIXEL_SCALE, &[scale_x, scale_y, 0.0]),
+        Entry::doubles(MODEL_TIEPOINT, &[0.0, 0.0, 0.0, west, north, 0.0]),
+        Entry::shorts(GEO_KEY_DIRECTORY, &geo_key_directory()),
+        Entry::ascii(GDAL_NODATA, &NODATA.to_string()),
+    ];
+    if let Some(unit) = grid.unit {
+        let metadata = format!(
+            "<GDALMetadata><Item name=\"UNITTYPE\" sample=\"0\" role=\"unittype\">{}</Item></GDALMetadata>",
+            unit
+        );
+        entries.push(Entry::ascii(GDAL_METADATA, &metadata));
+    }
+    entries.sort_by_key(|entry| entry.tag);
+
+    // Values too long to fit in their entry follow the IFD
+    let ifd_offset = 8 + image.len();
+    let extra_offset = ifd_offset + 2 + entries.len() * 12 + 4;
+    let mut ifd = Vec::with_capacity(extra_offset - ifd_offset);
+    let mut extra = Vec::new();
+    ifd.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for entry in &entries {
+        ifd.extend_from_slice(&entry.tag.to_le_bytes());
+        ifd.extend_from_slice(&entry.kind.to_le_bytes());
+        ifd.extend_from_slice(&entry.count.to_le_bytes());
+        if entry.data.len() <= 4 {
+            let mut value = [0u8; 4];
+            value[..entry.data.len()].copy_from_slice(&entry.data);
+            ifd.extend_from_slice(&value);
+        } else {
+            ifd.extend_from_slice(&((extra_offset + extra.len()) as u32).to_le_bytes());
+            extra.extend_from_slice(&entry.data);
+            // Values have to start on a word boundary
+            if extra.len() % 2 == 1 {
+                extra.push(0);
+            }
+        }
+    }
+    // No further IFDs
+    ifd.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(b"II")?;
+    writer.write_all(&42u16.to_le_bytes())?;
+    writer.write_all(&(ifd_offset as u32).to_le_bytes())?;
+    writer.write_all(&image)?;
+    writer.write_all(&ifd)?;
+    writer.write_all(&extra)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Position of an IFD entry's value and how to read it
+#[derive(Clone, Copy)]
+struct Field {
+    kind: u16,
+    count: usize,
+    offset: usize,
+}
+
+struct Tiff {
+    bytes: Vec<u8>,
+    little_endian: bool,
+    fields: HashMap<u16, Field>,
+}
+
+impl Tiff {
+    fn parse(bytes: Vec<u8>) -> Result<Self, GeoTiffErr> {
+        let little_endian = match bytes.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err(GeoTiffErr::NotATiff),
+        };
+        let mut tiff = Self {
+            bytes,
+            little_endian,
+            fields: HashMap::new(),
+        };
+        match tiff.u16_at(2)? {
+            42 => (),
+            43 => return Err(GeoTiffErr::Unsupported(String::from("BigTIFF layout"))),
+            _ => return Err(GeoTiffErr::NotATiff),
+        }
+        // Only the first image is read
+        let ifd = tiff.u32_at(4)? as usize;
+        let count = tiff.u16_at(ifd)? as usize;
+        for i in 0..count {
+            let entry = ifd + 2 + i * 12;
+            let kind = tiff.u16_at(entry + 2)?;
+            let count = tiff.u32_at(entry + 4)? as usize;
+            let size = match kind {
+                BYTE | ASCII => 1,
+                SHORT | SSHORT => 2,
+                LONG | SLONG | FLOAT => 4,
+                DOUBLE => 8,
+                // Types the reader doesn't need
+                _ => continue,
+            };
+            let offset = if size * count <= 4 {
+                entry + 8
+            } else {
+                tiff.u32_at(entry + 8)? as usize
+            };
+            tiff.bytes(offset, size * count)?;
+            let tag = tiff.u16_at(entry)?;
+            tiff.fields.insert(
+                tag,
+                Field {
+                    kind,
+                    count,
+                    offset,
+                },
+            );
+        }
+        Ok(tiff)
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], GeoTiffErr> {
+        let end = offset.checked_add(len).ok_or(GeoTiffErr::Truncated)?;
+        self.bytes.get(offset..end).ok_or(GeoTiffErr::Truncated)
+    }
+
+    fn array<A: Default + AsMut<[u8]>>(&self, offset: usize) -> Result<A, GeoTiffErr> {
+        let mut array = A::default();
+        {
+            let bytes = array.as_mut();
+            bytes.copy_from_slice(self.bytes(offset, bytes.len())?);
+            if !self.little_endian {
+                bytes.reverse();
+            }
+        }
+        Ok(array)
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16, GeoTiffErr> {
+        Ok(u16::from_le_bytes(self.array(offset)?))
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, GeoTiffErr> {
+        Ok(u32::from_le_bytes(self.array(offset)?))
+    }
+
+    fn field(&self, tag: u16) -> Option<Field> {
+        self.fields.get(&tag).cloned()
+    }
+
+    // Reads the value at index of a numeric field of any type
+    fn number(&self, field: Field, index: usize) -> Result<f64, GeoTiffErr> {
+        let value = match field.kind {
+            BYTE => f64::from(self.bytes(field.offset + index, 1)?[0]),
+            SHORT => f64::from(self.u16_at(field.offset + index * 2)?),
+            SSHORT => f64::from(i16::from_le_bytes(self.array(field.offset + index * 2)?)),
+            LONG => f64::from(self.u32_at(field.offset + index * 4)?),
+            SLONG => f64::from(i32::from_le_bytes(self.array(field.offset + index * 4)?)),
+            FLOAT => f64::from(f32::from_le_bytes(self.array(field.offset + index * 4)?)),
+            DOUBLE => f64::from_le_bytes(self.array(field.offset + index * 8)?),
+            _ => {
+                return Err(GeoTiffErr::Unsupported(format!(
+                    "field type {}",
+                    field.kind
+                )))
+            }
+        };
+        Ok(value)
+    }
+
+    fn numbers(&self, tag: u16, name: &'static str) -> Result<Vec<f64>, GeoTiffErr> {
+        let field = self.field(tag).ok_or(GeoTiffErr::MissingTag(name))?;
+        (0..field.count)
+            .map(|index| self.number(field, index))
+            .collect()
+    }
+
+    // First value of a field that has to be present, an empty field counts as corrupt
+    fn first_number(&self, tag: u16, name: &'static str) -> Result<f64, GeoTiffErr> {
+        let field = self.field(tag).ok_or(GeoTiffErr::MissingTag(name))?;
+        if field.count == 0 {
+            return Err(GeoTiffErr::Truncated);
+        }
+        self.number(field, 0)
+    }
+
+    fn number_or(&self, tag: u16, default: f64) -> Result<f64, GeoTiffErr> {
+        match self.field(tag) {
+            Some(field) if field.count == 0 => Err(GeoTiffErr::Truncated),
+            Some(field) => self.number(field, 0),
+            None => Ok(default),
+        }
+    }
+
+    fn text(&self, tag: u16) -> Result<Option<String>, GeoTiffErr> {
+        let field = match self.field(tag) {
+            Some(field) if field.kind == ASCII => field,
+            _ => return Ok(None),
+        };
+        let bytes = self.bytes(field.offset, field.count)?;
+        let text = String::from_utf8_lossy(bytes);
+        Ok(Some(String::from(text.trim_end_matches('\0').trim())))
+    }
+
+    // Reads a sample of the given byte size and sample format
+    fn sample(&self, offset: usize, size: usize, format: u16) -> Result<f64, GeoTiffErr> {
+        let value = match (format, size) {
+            (UNSIGNED_INT, 1) => f64::from(self.bytes(offset, 1)?[0]),
+            (SIGNED_INT, 1) => f64::from(self.bytes(offset, 1)?[0] as i8),
+            (UNSIGNED_INT, 2) => f64::from(self.u16_at(offset)?),
+            (SIGNED_INT, 2) => f64::from(i16::from_le_bytes(self.array(offset)?)),
+            (UNSIGNED_INT, 4) => f64::from(self.u32_at(offset)?),
+            (SIGNED_INT, 4) => f64::from(i32::from_le_bytes(self.array(offset)?)),
+            (IEEE_FLOAT, 4) => f64::from(f32::from_le_bytes(self.array(offset)?)),
+            (IEEE_FLOAT, 8) => f64::from_le_bytes(self.array(offset)?),
+            _ => {
+                return Err(GeoTiffErr::Unsupported(format!(
+                    "{} bit samples of format {}",
+                    size * 8,
+                    format
+                )))
+            }
+        };
+        Ok(value)
+    }
+}
+
+// Unit written to the GDAL metadata by write_geotiff, or by GDAL as a band's unit type
+fn metadata_unit(metadata: &str) -> Option<Unit> {
+    let start = metadata.find("role=\"unittype\">")? + "role=\"unittype\">".len();
+    let end = start + metadata[start..].find('<')?;
+    metadata[start..end].parse().ok()
+}
+
+// Reads the first band of an uncompressed, stripped GeoTIFF in geographic coordinates. Any
+// integer or float sample type is read, nodata and NaN samples become None
+pub fn read_geotiff(path: impl AsRef<Path>) -> Result<HeatMap<Option<f32>>, Box<Error>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let tiff = Tiff::parse(bytes)?;
+
+    let width = tiff.first_number(IMAGE_WIDTH, "ImageWidth")? as usize;
+    let height = tiff.first_number(IMAGE_LENGTH, "ImageLength")? as usize;
+    if tiff.field(TILE_WIDTH).is_some() {
+        return Err(Box::new(GeoTiffErr::Unsupported(String::from(
+            "tiled layout",
+        ))));
+    }
+    let compression = tiff.number_or(COMPRESSION, 1.0)?;
+    if compression != 1.0 {
+        return Err(Box::new(GeoTiffErr::Unsupported(format!(
+            "compression {}",
+            compression
+        ))));
+    }
+    let samples_per_pixel = tiff.number_or(SAMPLES_PER_PIXEL, 1.0)? as usize;
+    if samples_per_pixel > 1 && tiff.number_or(PLANAR_CONFIGURATION, 1.0)? != 1.0 {
+        return Err(Box::new(GeoTiffErr::Unsupported(String::from(
+            "separate planes",
+        ))));
+    }
+    let bits_per_sample = tiff.first_number(BITS_PER_SAMPLE, "BitsPerSample")? as usize;
+    let sample_size = bits_per_sample / 8;
+    if sample_size == 0 {
+        return Err(Box::new(GeoTiffErr::Unsupported(format!(
+            "{} bit samples",
+            bits_per_sample
+        ))));
+    }
+    let sample_format = tiff.number_or(SAMPLE_FORMAT, f64::from(UNSIGNED_INT))? as u16;
+    let rows_per_strip = tiff.number_or(ROWS_PER_STRIP, height as f64)? as usize;
+    let strip_offsets = tiff.numbers(STRIP_OFFSETS, "StripOffsets")?;
+    let nodata = match tiff.text(GDAL_NODATA)? {
+        Some(text) => text.parse::<f64>().ok(),
+        None => None,
+    };
+
+    // Checked against the file before allocating, so a corrupt header can't ask for more cells
+    // than the file holds
+    let rows_per_strip = rows_per_strip.max(1);
+    let pixel_size = sample_size
+        .checked_mul(samples_per_pixel)
+        .ok_or(GeoTiffErr::Truncated)?;
+    let row_size = width.checked_mul(pixel_size).ok_or(GeoTiffErr::Truncated)?;
+    let cells = width.checked_mul(height).ok_or(GeoTiffErr::Truncated)?;
+    for (strip, &offset) in strip_offsets.iter().enumerate() {
+        let first_row = strip.saturating_mul(rows_per_strip);
+        if first_row >= height {
+            break;
+        }
+        let rows = rows_per_strip.min(height - first_row);
+        let size = rows.checked_mul(row_size).ok_or(GeoTiffErr::Truncated)?;
+        tiff.bytes(offset as usize, size)?;
+    }
+    if strip_offsets.len() < height.div_ceil(rows_per_strip) {
+        return Err(Box::new(GeoTiffErr::Truncated));
+    }
+
+    let mut values = vec![None; cells];
+    for row in 0..height {
+        let strip = strip_offsets[row / rows_per_strip];
+        let start = strip as usize + (row % rows_per_strip) * row_size;
+        // TIFF rows run north to south, grid rows south to north
+        let y = height - 1 - row;
+        for x in 0..width {
+            let value = tiff.sample(start + x * pixel_size, sample_size, sample_format)?;
+            let missing = value.is_nan() || nodata.is_some_and(|nodata| value == nodata);
+            if !missing {
+                values[x + y * width] = Some(value as f32);
+            }
+        }
+    }
+
+    let range = georeference(&tiff, width, height)?;
+    let unit = match tiff.text(GDAL_METADATA)? {
+        Some(metadata) => metadata_unit(&metadata),
+        None => None,
+    };
+    let grid = Grid::new_from_values(width, height, values).with_unit(unit);
+    Ok(HeatMap::new(grid, range))
+}
+
+// Finds the range of cell centres from the tie point and pixel scale
+fn georeference(tiff: &Tiff, width: usize, height: usize) -> Result<RangeBox<f32>, GeoTiffErr> {
+    let scale = tiff.numbers(MODEL_PIXEL_SCALE, "ModelPixelScale")?;
+    let tiepoint = tiff.numbers(MODEL_TIEPOINT, "ModelTiepoint")?;
+    if scale.len() < 2 || tiepoint.len() < 6 {
+        return Err(GeoTiffErr::Truncated);
+    }
+    if scale[1] <= 0.0 {
+        return Err(GeoTiffErr::Unsupported(String::from("south up rasters")));
+    }
+
+    let mut raster_type = RASTER_PIXEL_IS_AREA;
+    if let Ok(keys) = tiff.numbers(GEO_KEY_DIRECTORY, "GeoKeyDirectory") {
+        for key in keys.chunks(4).skip(1) {
+            if key.len() < 4 || key[1] != 0.0 {
+                continue;
+            }
+            match key[0] as u16 {
+                GT_MODEL_TYPE if key[3] as u16 != MODEL_TYPE_GEOGRAPHIC => {
+                    return Err(GeoTiffErr::Unsupported(String::from(
+                        "projected coordinate systems",
+                    )))
+                }
+                GT_RASTER_TYPE => raster_type = key[3] as u16,
+                _ => (),
+            }
+        }
+    }
+
+    // Raster (i, j) lies at model (x, y). Pixel is point tie points are cell centres rather
+    // than cell corners
+    let (scale_x, scale_y) = (scale[0], scale[1]);
+    let mut west = tiepoint[3] - tiepoint[0] * scale_x;
+    let mut north = tiepoint[4] + tiepoint[1] * scale_y;
+    if raster_type == RASTER_PIXEL_IS_POINT {
+        west -= scale_x / 2.0;
+        north += scale_y / 2.0;
+    }
+    let from_x = west + scale_x / 2.0;
+    let from_y = north - (height as f64 - 0.5) * scale_y;
+    Ok(RangeBox::new(
+        Range::new(from_x as f32, (from_x + width as f64 * scale_x) as f32),
+        Range::new(from_y as f32, (from_y + height as f64 * scale_y) as f32),
+    ))
+}
+
+impl HeatMap<Option<f32>> {
+    pub fn from_geotiff(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        read_geotiff(path)
+    }
+
+    pub fn save_geotiff(&self, path: impl AsRef<Path>) -> Result<(), Box<Error>> {
+        write_geotiff(path, &self.grid, &self.range)
+    }
+}
@@ -0,0 +1,258 @@
+use draw::blend_pixel;
+use font::{draw_text, text_height, text_width};
+use image::{Rgba, RgbaImage};
+use math::{Range, RangeBox};
+
+// Intervals in degrees tried when picking one for a range, smallest first
+const INTERVALS: [f32; 11] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 45.0, 90.0];
+// Pixels between a label and the edge of the map or its background box
+const LABEL_MARGIN: u32 = 2;
+
+// Latitude and longitude lines over a map, with the lines labelled in degrees along the
+// bottom and left edges
+#[derive(Clone, Debug)]
+pub struct Graticule {
+    // Degrees between lines of longitude and of latitude
+    pub longitude_interval: f32,
+    pub latitude_interval: f32,
+    pub line_colour: Rgba<u8>,
+    pub line_width: u32,
+    pub labels: bool,
+    pub label_colour: Rgba<u8>,
+    // Box drawn behind labels so they can be read over the map
+    pub label_background: Option<Rgba<u8>>,
+    pub scale: u32,
+}
+
+// Eg. "30°N", "120°W" and "0°"
+pub fn format_degrees(value: f32, decimals: usize, positive: char, negative: char) -> String {
+    let text = format!("{:.*}", decimals, value.abs());
+    if text.chars().all(|c| c == '0' || c == '.') {
+        return format!("{}°", text);
+    }
+    let hemisphere = if value > 0.0 { positive } else { negative };
+    format!("{}°{}", text, hemisphere)
+}
+
+// Decimals needed to show multiples of the interval
+fn decimals(interval: f32) -> usize {
+    (0..3)
+        .find(|&decimals| {
+            let scaled = interval * 10f32.powi(decimals as i32);
+            (scaled - scaled.round()).abs() < 1e-3
+        })
+        .unwrap_or(3)
+}
+
+// Multiples of the interval within the range, edges included
+pub fn lines_within(range: Range<f32>, interval: f32) -> Vec<f32> {
+    if interval <= 0.0 || range.length() <= 0.0 {
+        return Vec::new();
+    }
+    let first = (range.from / interval - 1e-4).ceil() as i64;
+    let last = (range.to / interval + 1e-4).floor() as i64;
+    (first..=last).map(|i| i as f32 * interval).collect()
+}
+
+// Interval giving at least count lines over the length
+pub fn pick_interval(length: f32, count: usize) -> f32 {
+    INTERVALS
+        .iter()
+        .rev()
+        .cloned()
+        .find(|&interval| length / interval >= count as f32)
+        .unwrap_or(INTERVALS[0])
+}
+
+impl Graticule {
+    pub fn new(interval: f32) -> Self {
+        Self {
+            longitude_interval: interval,
+            latitude_interval: interval,
+            line_colour: Rgba {
+                data: [255, 255, 255, 128],
+            },
+            line_width: 1,
+            labels: true,
+            label_colour: Rgba {
+                data: [255, 255, 255, 255],
+            },
+            label_background: Some(Rgba {
+                data: [0, 0, 0, 160],
+            }),
+            scale: 1,
+        }
+    }
+
+    // Intervals that give a few lines each way over the range, eg. 30° for the world and 10°
+    // for Europe
+    pub fn for_range(range: &RangeBox<f32>) -> Self {
+        let mut graticule = Self::new(pick_interval(range.horizontal.length(), 4));
+        graticule.latitude_interval = pick_interval(range.vertical.length(), 3);
+        graticule
+    }
+
+    pub fn with_intervals(mut self, longitude_interval: f32, latitude_interval: f32) -> Self {
+        self.longitude_interval = longitude_interval;
+        self.latitude_interval = latitude_interval;
+        self
+    }
+
+    pub fn with_line_colour(mut self, colour: Rgba<u8>) -> Self {
+        self.line_colour = colour;
+        self
+    }
+
+    pub fn with_line_width(mut self, width: u32) -> Self {
+        self.line_width = width.max(1);
+        self
+    }
+
+    pub fn with_labels(mut self, labels: bool) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn with_label_colours(mut self, colour: Rgba<u8>, background: Option<Rgba<u8>>) -> Self {
+        self.label_colour = colour;
+        self.label_background = background;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn longitudes(&self, range: &RangeBox<f32>) -> Vec<f32> {
+        lines_within(range.horizontal, self.longitude_interval)
+    }
+
+    pub fn latitudes(&self, range: &RangeBox<f32>) -> Vec<f32> {
+        lines_within(range.vertical, self.latitude_interval)
+    }
+
+    pub fn longitude_label(&self, longitude: f32) -> String {
+        // Longitudes past the antimeridian are labelled as they would be on the other side
+        let wrapped = (longitude + 180.0).rem_euclid(360.0) - 180.0;
+        let decimals = decimals(self.longitude_interval);
+        if wrapped == -180.0 {
+            return format!("{:.*}°", decimals, 180.0);
+        }
+        format_degrees(wrapped, decimals, 'E', 'W')
+    }
+
+    pub fn latitude_label(&self, latitude: f32) -> String {
+        format_degrees(latitude, decimals(self.latitude_interval), 'N', 'S')
+    }
+
+    // Pixel column or row of the line in an image of the given size covering the range. The
+    // image's pixel centres are at the same positions as in MapRenderer::render
+    fn pixel(value: f32, range: Range<f32>, size: u32) -> i64 {
+        let position = (value - range.from) / range.length() * size as f32;
+        (position.floor() as i64).clamp(0, i64::from(size) - 1)
+    }
+
+    // Draws the graticule over an image of the map covering the range. The image can be
+    // transparent to make an overlay
+    pub fn draw(&self, image: &mut RgbaImage, range: &RangeBox<f32>) {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return;
+        }
+        let half = i64::from(self.line_width / 2);
+        let longitudes = self.longitudes(range);
+        let latitudes = self.latitudes(range);
+        // Columns of the longitude lines, latitude lines skip them so crossings aren't drawn
+        // twice
+        let mut columns = vec![false; width as usize];
+        for &longitude in &longitudes {
+            let x = Self::pixel(longitude, range.horizontal, width) - half;
+            for column in x..x + i64::from(self.line_width) {
+                if column >= 0 && column < i64::from(width) {
+                    columns[column as usize] = true;
+                }
+            }
+        }
+        for (x, _) in columns.iter().enumerate().filter(|(_, &line)| line) {
+            for y in 0..i64::from(height) {
+                blend_pixel(image, x as i64, y, self.line_colour, 1.0);
+            }
+        }
+        for &latitude in &latitudes {
+            // Rows start at the top of the image
+            let y = i64::from(height) - 1 - Self::pixel(latitude, range.vertical, height) - half;
+            for (x, _) in columns.iter().enumerate().filter(|(_, &line)| !line) {
+                for row in y..y + i64::from(self.line_width) {
+                    blend_pixel(image, x as i64, row, self.line_colour, 1.0);
+                }
+            }
+        }
+        if !self.labels {
+            return;
+        }
+        // Lines on the edges of the map aren't labelled, their labels would collide in the
+        // corners. Labels that would overlap the one before are skipped
+        let interior = |value: f32, range: Range<f32>| {
+            let tolerance = range.length() * 1e-4;
+            value > range.from + tolerance && value < range.to - tolerance
+        };
+        let text_h = i64::from(text_height(self.scale));
+        let margin = i64::from(LABEL_MARGIN);
+        let mut last_right = i64::MIN;
+        for &longitude in longitudes
+            .iter()
+            .filter(|&&longitude| interior(longitude, range.horizontal))
+        {
+            let label = self.longitude_label(longitude);
+            let text_w = i64::from(text_width(&label, self.scale));
+            let x = Self::pixel(longitude, range.horizontal, width) - text_w / 2;
+            let x = x.clamp(margin, (i64::from(width) - text_w - margin).max(margin));
+            if x - 2 * margin <= last_right {
+                continue;
+            }
+            last_right = x + text_w;
+            self.draw_label(image, &label, x, i64::from(height) - text_h - 2 * margin);
+        }
+        // Labels above the longitude labels, from the top down
+        let lowest = (i64::from(height) - 2 * text_h - 5 * margin).max(margin);
+        let mut last_bottom = i64::MIN;
+        for &latitude in latitudes
+            .iter()
+            .rev()
+            .filter(|&&latitude| interior(latitude, range.vertical))
+        {
+            let label = self.latitude_label(latitude);
+            let y =
+                i64::from(height) - 1 - Self::pixel(latitude, range.vertical, height) - text_h / 2;
+            let y = y.clamp(margin, lowest);
+            if y - 2 * margin <= last_bottom {
+                continue;
+            }
+            last_bottom = y + text_h;
+            self.draw_label(image, &label, 2 * margin, y);
+        }
+    }
+
+    fn draw_label(&self, image: &mut RgbaImage, label: &str, x: i64, y: i64) {
+        if let Some(background) = self.label_background {
+            let margin = i64::from(LABEL_MARGIN);
+            let (left, top) = ((x - margin).max(0), (y - margin).max(0));
+            let right = x + i64::from(text_width(label, self.scale)) + margin;
+            let bottom = y + i64::from(text_height(self.scale)) + margin;
+            for py in top..bottom {
+                for px in left..right {
+                    blend_pixel(image, px, py, background, 1.0);
+                }
+            }
+        }
+        draw_text(image, label, x, y, self.scale, self.label_colour);
+    }
+
+    // Transparent image of just the graticule, eg. to draw over the map in the viewer
+    pub fn overlay(&self, width: u32, height: u32, range: &RangeBox<f32>) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(width, height, Rgba { data: [0, 0, 0, 0] });
+        self.draw(&mut image, range);
+        image
+    }
+}
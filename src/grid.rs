@@ -1,25 +1,26 @@
-use bincode::{deserialize_from, serialize_into};
 use data::CSum;
 use glium::backend::glutin::Display;
-use glium::texture::{texture2d::Texture2d, RawImage2d};
+use glium::texture::{texture2d::Texture2d, MipmapsOption, RawImage2d, UncompressedFloatFormat};
+use grid_file::{GridFile, GridValue};
 use math::{Range, RectIter};
 use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
 use std::ops::{Index, IndexMut};
 use std::path::Path;
+use units::{check_same_units, Unit, UnitErr};
 
-// Top left value will be stored first and the data will be separated by each horizontal layer
+// The [0, 0] value will be stored first and the data will be separated by each horizontal layer
 // Eg. [1 2 3 4 5] = [1 2 3 4 5 1 2 3 4 5]
 //     [1 2 3 4 5]
+// In a HeatMap row 0 is the bottom (southern) edge of the range, so formats that list the
+// northern row first are read and written in reverse
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Grid<T: Copy> {
     // Ratio in terms of vertical / horizontal
     pub horizontal: usize,
     pub vertical: usize,
     pub values: Vec<T>,
+    pub unit: Option<Unit>,
 }
 
 impl<T: Copy> Grid<T> {
@@ -28,6 +29,7 @@ impl<T: Copy> Grid<T> {
             horizontal,
             vertical,
             values: vec![default_value; (vertical) * (horizontal)],
+            unit: None,
         }
     }
 
@@ -36,9 +38,15 @@ impl<T: Copy> Grid<T> {
             horizontal,
             vertical,
             values,
+            unit: None,
         }
     }
 
+    pub fn with_unit(mut self, unit: Option<Unit>) -> Self {
+        self.unit = unit;
+        self
+    }
+
     pub fn values_ref(&self) -> &[T] {
         &self.values
     }
@@ -63,31 +71,25 @@ impl<T: Copy> Grid<T> {
         }
     }
 
+    // The unit is kept, clear it if func changes what the values measure
     pub fn into_grid_with<V: Copy, U: Fn(&T) -> Option<V>>(&self, func: U) -> Grid<Option<V>> {
         let mut grid_values = Vec::with_capacity(self.values.len());
         for value in self.values_ref() {
             grid_values.push(func(value));
         }
-        Grid::new_from_values(self.horizontal, self.vertical, grid_values)
+        Grid::new_from_values(self.horizontal, self.vertical, grid_values).with_unit(self.unit)
     }
 }
 
-impl<T: Copy + Serialize> Grid<T>
-where
-    for<'de> T: Deserialize<'de>,
-{
+// Saves without a georeference or metadata, use GridFile to keep them
+impl<T: GridValue> Grid<T> {
     pub fn save_to_bin(&self, path: impl AsRef<Path>) -> Result<(), Box<Error>> {
-        let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-        serialize_into(writer, &self)?;
-        Ok(())
+        GridFile::new(self.clone()).save(path)
     }
 
+    // Also reads grids saved before the file format was versioned
     pub fn load_from_bin(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let grid = deserialize_from(reader)?;
-        Ok(grid)
+        Ok(GridFile::load(path)?.grid)
     }
 }
 
@@ -273,6 +275,46 @@ impl Grid<Option<f32>> {
         )
     }
 
+    // Texture for shaders/colormap.glsl. Red holds the value scaled so the range is 0 to 1 and
+    // isn't clamped, so values outside the range get the under and over colours. Green is 1
+    // where there is a value
+    pub fn into_normalised_texture(
+        &self,
+        display: &Display,
+        range: Option<Range<f32>>,
+    ) -> (Texture2d, Range<f32>) {
+        let range = range.unwrap_or_else(|| {
+            Range::new(
+                self.min_option().expect("No minimum value"),
+                self.max_option().expect("No maximum value"),
+            )
+        });
+        let mut rgb = Vec::with_capacity(self.values.len() * 3);
+        for y in 0..self.vertical {
+            for x in 0..self.horizontal {
+                let (value, valid) = match self[[x, y]] {
+                    Some(value) if range.length() != 0.0 => {
+                        ((value - range.from) / range.length(), 1.0)
+                    }
+                    Some(_) => (0.5, 1.0),
+                    None => (0.0, 0.0),
+                };
+                rgb.push(value);
+                rgb.push(valid);
+                rgb.push(0.0);
+            }
+        }
+        (
+            Texture2d::with_format(
+                display,
+                RawImage2d::from_raw_rgb(rgb, ((self.horizontal) as u32, (self.vertical) as u32)),
+                UncompressedFloatFormat::F32F32F32,
+                MipmapsOption::NoMipmap,
+            ).expect("Failed to create texture"),
+            range,
+        )
+    }
+
     pub fn into_texture_with_function<U>(&self, display: &Display, func: U) -> Texture2d
     where
         U: Fn(&Grid<Option<f32>>, [usize; 2]) -> f32,
@@ -310,7 +352,9 @@ impl Grid<Option<f32>> {
         return closest_points;
     }
 
-    pub fn compare_to(&self, other: &Grid<Option<f32>>) -> Vec<(f32, f32)> {
+    // Values of the same quantity have to be converted to the same unit first
+    pub fn compare_to(&self, other: &Grid<Option<f32>>) -> Result<Vec<(f32, f32)>, UnitErr> {
+        check_same_units(self.unit, other.unit)?;
         let x_ratio = other.horizontal as f32 / self.horizontal as f32;
         let y_ratio = other.vertical as f32 / self.vertical as f32;
         let mut values = Vec::new();
@@ -326,7 +370,67 @@ impl Grid<Option<f32>> {
                 }
             }
         }
-        return values;
+        Ok(values)
+    }
+
+    // Applies func to every pair of cells, both grids have to be the same size and in the same
+    // unit. The result keeps the unit
+    pub fn combine_with<U>(&self, other: &Grid<Option<f32>>, func: U) -> Result<Grid<Option<f32>>, UnitErr>
+    where
+        U: Fn(f32, f32) -> f32,
+    {
+        if let (Some(first), Some(second)) = (self.unit, other.unit) {
+            first.check_compatible(second)?;
+            if first != second {
+                return Err(UnitErr::Mismatch(first, second));
+            }
+        }
+        if self.horizontal != other.horizontal || self.vertical != other.vertical {
+            return Err(UnitErr::SizeMismatch(
+                (self.horizontal, self.vertical),
+                (other.horizontal, other.vertical),
+            ));
+        }
+        let values = self
+            .values
+            .iter()
+            .zip(other.values.iter())
+            .map(|(&first, &second)| match (first, second) {
+                (Some(first), Some(second)) => Some(func(first, second)),
+                _ => None,
+            })
+            .collect();
+        let unit = self.unit.or(other.unit);
+        Ok(Grid::new_from_values(self.horizontal, self.vertical, values).with_unit(unit))
+    }
+
+    // Converts absolute values, such as temperatures, to the unit
+    pub fn convert_to(&self, unit: Unit) -> Result<Grid<Option<f32>>, UnitErr> {
+        self.convert_with(unit, Unit::convert)
+    }
+
+    // Converts differences, such as standard deviations or ranges, to the unit
+    pub fn convert_interval_to(&self, unit: Unit) -> Result<Grid<Option<f32>>, UnitErr> {
+        self.convert_with(unit, Unit::convert_interval)
+    }
+
+    fn convert_with<U>(&self, unit: Unit, convert: U) -> Result<Grid<Option<f32>>, UnitErr>
+    where
+        U: Fn(&Unit, f32, Unit) -> Result<f32, UnitErr>,
+    {
+        let from = match self.unit {
+            Some(from) => from,
+            None => return Err(UnitErr::Missing),
+        };
+        from.check_compatible(unit)?;
+        let mut values = Vec::with_capacity(self.values.len());
+        for &value in &self.values {
+            values.push(match value {
+                Some(value) => Some(convert(&from, value, unit)?),
+                None => None,
+            });
+        }
+        Ok(Grid::new_from_values(self.horizontal, self.vertical, values).with_unit(Some(unit)))
     }
 
     pub fn into_range_grid(&self, radius: i32) -> Grid<Option<f32>> {
@@ -373,7 +477,7 @@ impl Grid<Option<f32>> {
         for index in 0..vec.len() {
             grid_vec.push(vec[index].1);
         }
-        Grid::new_from_values(self.horizontal, self.vertical, grid_vec)
+        Grid::new_from_values(self.horizontal, self.vertical, grid_vec).with_unit(self.unit)
     }
 
     pub fn range_grid_from_closest(&self, radius: i32) -> Grid<Option<f32>> {
@@ -420,7 +524,7 @@ impl Grid<Option<f32>> {
         for index in 0..vec.len() {
             grid_vec.push(vec[index].1);
         }
-        Grid::new_from_values(self.horizontal, self.vertical, grid_vec)
+        Grid::new_from_values(self.horizontal, self.vertical, grid_vec).with_unit(self.unit)
     }
 }
 
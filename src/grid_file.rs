@@ -0,0 +1,356 @@
+use bincode::{deserialize_from, serialize_into};
+use data::{CSum, YearlyData};
+use grid::Grid;
+use math::{Range, RangeBox};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use units::Unit;
+
+// Files start with the magic bytes, then the format version, the header and the grid values.
+// Files written before the format existed are raw bincode grids and are read as version 0
+const MAGIC: [u8; 8] = *b"GSOMGRID";
+pub const FORMAT_VERSION: u32 = 1;
+
+// Values that can be stored in a grid file. The kind is written to the header so a grid of one
+// value type isn't read as another
+pub trait GridValue: Copy + Serialize + DeserializeOwned {
+    fn kind() -> &'static str;
+}
+
+impl GridValue for f32 {
+    fn kind() -> &'static str {
+        "f32"
+    }
+}
+
+impl GridValue for Option<f32> {
+    fn kind() -> &'static str {
+        "Option<f32>"
+    }
+}
+
+impl GridValue for CSum<f32> {
+    fn kind() -> &'static str {
+        "CSum<f32>"
+    }
+}
+
+impl GridValue for Option<CSum<f32>> {
+    fn kind() -> &'static str {
+        "Option<CSum<f32>>"
+    }
+}
+
+impl GridValue for YearlyData<f32> {
+    fn kind() -> &'static str {
+        "YearlyData<f32>"
+    }
+}
+
+impl GridValue for Option<YearlyData<f32>> {
+    fn kind() -> &'static str {
+        "Option<YearlyData<f32>>"
+    }
+}
+
+// Longitude and latitude of the grid's edges in degrees
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Georeference {
+    pub west: f32,
+    pub east: f32,
+    pub south: f32,
+    pub north: f32,
+}
+
+impl Georeference {
+    pub fn from_range(range: &RangeBox<f32>) -> Self {
+        Self {
+            west: range.horizontal.from,
+            east: range.horizontal.to,
+            south: range.vertical.from,
+            north: range.vertical.to,
+        }
+    }
+
+    pub fn range(&self) -> RangeBox<f32> {
+        RangeBox::new(
+            Range::new(self.west, self.east),
+            Range::new(self.south, self.north),
+        )
+    }
+}
+
+// Describes what the values of a grid are, eg. the standard deviation of TAVG
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct GridMetadata {
+    // Eg. a GSOM element code such as "TAVG"
+    pub variable: Option<String>,
+    // Eg. "mean" or "standard deviation"
+    pub statistic: Option<String>,
+    // Seconds since the unix epoch, set when the file is saved
+    pub created: Option<u64>,
+    // Settings the grid was made with, eg. ("years", "1981-2010")
+    pub parameters: Vec<(String, String)>,
+}
+
+impl GridMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_variable(mut self, variable: &str) -> Self {
+        self.variable = Some(String::from(variable));
+        self
+    }
+
+    pub fn with_statistic(mut self, statistic: &str) -> Self {
+        self.statistic = Some(String::from(statistic));
+        self
+    }
+
+    pub fn with_parameter(mut self, name: &str, value: &str) -> Self {
+        self.parameters
+            .push((String::from(name), String::from(value)));
+        self
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Everything in a grid file except the values, can be read without reading the whole file
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GridHeader {
+    // Version the file was written with, older files are migrated when read. Written before the
+    // header rather than in it, so the header layout can change between versions
+    #[serde(skip)]
+    pub version: u32,
+    pub kind: String,
+    pub horizontal: usize,
+    pub vertical: usize,
+    pub unit: Option<Unit>,
+    pub georeference: Option<Georeference>,
+    pub metadata: GridMetadata,
+}
+
+// Layout of grids saved before the format was versioned
+#[derive(Deserialize)]
+struct LegacyGrid<T> {
+    horizontal: usize,
+    vertical: usize,
+    values: Vec<T>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GridFileErr {
+    // The file has no magic header and isn't a legacy grid either
+    NotAGridFile,
+    UnsupportedVersion(u32),
+    // The file holds a grid of different values than were asked for
+    WrongKind { expected: String, found: String },
+    // The number of values doesn't match the size in the header
+    SizeMismatch { expected: usize, found: usize },
+    // A heat map was loaded from a grid saved without its bounds
+    MissingGeoreference,
+}
+
+impl Display for GridFileErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            GridFileErr::NotAGridFile => write!(f, "File is not a grid file"),
+            GridFileErr::UnsupportedVersion(version) => write!(
+                f,
+                "Grid file version {} is newer than the supported version {}",
+                version, FORMAT_VERSION
+            ),
+            GridFileErr::WrongKind { expected, found } => write!(
+                f,
+                "Expected a grid of {} but the file holds a grid of {}",
+                expected, found
+            ),
+            GridFileErr::SizeMismatch { expected, found } => write!(
+                f,
+                "Grid file should hold {} values but holds {}",
+                expected, found
+            ),
+            GridFileErr::MissingGeoreference => {
+                write!(f, "Grid file has no georeference to make a heat map with")
+            }
+        }
+    }
+}
+
+impl Error for GridFileErr {}
+
+// A grid together with where it lies on the map and what its values are
+#[derive(Clone, Debug)]
+pub struct GridFile<T: Copy> {
+    pub grid: Grid<T>,
+    pub range: Option<RangeBox<f32>>,
+    pub metadata: GridMetadata,
+    // Version the grid was read from, FORMAT_VERSION for new grids
+    pub version: u32,
+}
+
+impl<T: GridValue> GridFile<T> {
+    pub fn new(grid: Grid<T>) -> Self {
+        Self {
+            grid,
+            range: None,
+            metadata: GridMetadata::default(),
+            version: FORMAT_VERSION,
+        }
+    }
+
+    pub fn with_range(mut self, range: RangeBox<f32>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: GridMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn header(&self) -> GridHeader {
+        grid_header(&self.grid, self.range.as_ref(), &self.metadata)
+    }
+
+    // Always writes the current version
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<Error>> {
+        save_grid(path, &self.grid, self.range.as_ref(), &self.metadata)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let mut reader = BufReader::new(File::open(&path)?);
+        let header = match read_header_from(&mut reader)? {
+            Some(header) => header,
+            None => return Self::load_legacy(path),
+        };
+        if header.kind != T::kind() {
+            return Err(Box::new(GridFileErr::WrongKind {
+                expected: String::from(T::kind()),
+                found: header.kind,
+            }));
+        }
+        let values: Vec<T> = deserialize_from(&mut reader)?;
+        let expected = header.horizontal * header.vertical;
+        if values.len() != expected {
+            return Err(Box::new(GridFileErr::SizeMismatch {
+                expected,
+                found: values.len(),
+            }));
+        }
+        let grid = Grid::new_from_values(header.horizontal, header.vertical, values)
+            .with_unit(header.unit);
+        Ok(Self {
+            grid,
+            range: header.georeference.map(|georeference| georeference.range()),
+            metadata: header.metadata,
+            version: header.version,
+        })
+    }
+
+    // Version 0 files have no header, so the kind can't be checked. A size that doesn't match the
+    // values is taken to mean the file is something else
+    fn load_legacy(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let legacy: LegacyGrid<T> = match deserialize_from(reader) {
+            Ok(legacy) => legacy,
+            Err(_) => return Err(Box::new(GridFileErr::NotAGridFile)),
+        };
+        let expected = legacy.horizontal.checked_mul(legacy.vertical);
+        if expected != Some(legacy.values.len()) || legacy.values.is_empty() {
+            return Err(Box::new(GridFileErr::NotAGridFile));
+        }
+        let grid = Grid::new_from_values(legacy.horizontal, legacy.vertical, legacy.values);
+        Ok(Self {
+            version: 0,
+            ..Self::new(grid)
+        })
+    }
+}
+
+fn grid_header<T: GridValue>(
+    grid: &Grid<T>,
+    range: Option<&RangeBox<f32>>,
+    metadata: &GridMetadata,
+) -> GridHeader {
+    GridHeader {
+        version: FORMAT_VERSION,
+        kind: String::from(T::kind()),
+        horizontal: grid.horizontal,
+        vertical: grid.vertical,
+        unit: grid.unit,
+        georeference: range.map(Georeference::from_range),
+        metadata: metadata.clone(),
+    }
+}
+
+// Writes a grid in the current version without taking it into a GridFile, so large grids don't
+// have to be copied to be saved
+pub fn save_grid<T: GridValue>(
+    path: impl AsRef<Path>,
+    grid: &Grid<T>,
+    range: Option<&RangeBox<f32>>,
+    metadata: &GridMetadata,
+) -> Result<(), Box<Error>> {
+    let mut header = grid_header(grid, range, metadata);
+    if header.metadata.created.is_none() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        header.metadata.created = Some(now.as_secs());
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&MAGIC)?;
+    serialize_into(&mut writer, &FORMAT_VERSION)?;
+    serialize_into(&mut writer, &header)?;
+    serialize_into(&mut writer, &grid.values)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Returns None for files without the magic header, ie. legacy grids or other files
+fn read_header_from<R: Read>(reader: &mut R) -> Result<Option<GridHeader>, Box<Error>> {
+    let mut magic = [0u8; 8];
+    if reader.read_exact(&mut magic).is_err() || magic != MAGIC {
+        return Ok(None);
+    }
+    let version: u32 = deserialize_from(&mut *reader)?;
+    if version > FORMAT_VERSION {
+        return Err(Box::new(GridFileErr::UnsupportedVersion(version)));
+    }
+    // Version 1 is the first versioned layout, later versions migrate their header here
+    let mut header: GridHeader = deserialize_from(&mut *reader)?;
+    header.version = version;
+    Ok(Some(header))
+}
+
+// Reads only the header. Legacy grids have no header and fail with NotAGridFile
+pub fn read_header(path: impl AsRef<Path>) -> Result<GridHeader, Box<Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match read_header_from(&mut reader)? {
+        Some(header) => Ok(header),
+        None => Err(Box::new(GridFileErr::NotAGridFile)),
+    }
+}
+
+// Rewrites a grid file of an older version in the current version, returning the version it
+// was in
+pub fn migrate_file<T: GridValue>(path: impl AsRef<Path>) -> Result<u32, Box<Error>> {
+    let file: GridFile<T> = GridFile::load(&path)?;
+    if file.version != FORMAT_VERSION {
+        file.save(&path)?;
+    }
+    Ok(file.version)
+}
@@ -1,15 +1,20 @@
-use bincode::deserialize_from;
-use csv::Reader;
+use cache::PointCacheReader;
+use csv::{ErrorKind, Reader};
 use data::{CSum, CsvRecord, DataPoint, TemperaturePoint, YearlyData};
 use grid::*;
+use grid_file::{save_grid, GridFile, GridFileErr, GridMetadata, GridValue};
+use ingest::IngestFilter;
 use math::{Dimensions, Point, RangeBox};
+use report::{IngestReport, RejectReason};
+use station::StationTable;
 use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
+use std::fmt::Debug;
 use std::path::Path;
+use units::Unit;
 
 pub type TempMap = HeatMap<YearlyData<f32>>;
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct HeatMap<T: Copy> {
     pub grid: Grid<T>,
     pub range: RangeBox<f32>,
@@ -20,6 +25,16 @@ impl<T: Copy> HeatMap<T> {
         Self { grid, range }
     }
 
+    // Unit of the values added to the map, carried onto every grid made from it
+    pub fn with_unit(mut self, unit: Option<Unit>) -> Self {
+        self.grid.unit = unit;
+        self
+    }
+
+    pub fn unit(&self) -> Option<Unit> {
+        self.grid.unit
+    }
+
     pub fn unit_dims(&self) -> Dimensions<f32> {
         let dims = self.range.dims();
         Dimensions::new(
@@ -32,39 +47,55 @@ impl<T: Copy> HeatMap<T> {
         self.range.contains(point)
     }
 
-    fn point_to_grid_index(&self, point: Point<f32>) -> Option<[usize; 2]> {
-        if self.point_in_map(point) {
-            let unit_dims = self.unit_dims();
-            let x_offset = point.x - self.range.horizontal.from;
-            let y_offset = point.y - self.range.vertical.from;
-            let index = [
-                (x_offset / unit_dims.x).round() as usize,
-                (y_offset / unit_dims.y).round() as usize,
-            ];
-            if index[0] + index[1] * self.grid.horizontal >= self.grid.values.len() {
-                return None;
-            }
-            return Some(index);
+    // Finds the cell the point falls into
+    pub fn locate(&self, point: Point<f32>) -> Result<[usize; 2], RejectReason> {
+        if !self.point_in_map(point) {
+            return Err(RejectReason::OutOfBounds);
+        }
+        let unit_dims = self.unit_dims();
+        let x_offset = point.x - self.range.horizontal.from;
+        let y_offset = point.y - self.range.vertical.from;
+        let index = [
+            (x_offset / unit_dims.x).round() as usize,
+            (y_offset / unit_dims.y).round() as usize,
+        ];
+        if index[0] >= self.grid.horizontal || index[1] >= self.grid.vertical {
+            return Err(RejectReason::OutOfGrid);
         }
-        None
+        Ok(index)
     }
 
-    pub fn add_data<S: Copy, U>(&mut self, datapoint: &DataPoint<S>, add_func: U)
+    fn point_to_grid_index(&self, point: Point<f32>) -> Option<[usize; 2]> {
+        self.locate(point).ok()
+    }
+
+    pub fn add_data<S: Copy, U>(
+        &mut self,
+        datapoint: &DataPoint<S>,
+        add_func: U,
+    ) -> Result<(), RejectReason>
     where
         U: Fn(&mut Grid<T>, [usize; 2], &S),
     {
-        if let Some(index) = self.point_to_grid_index(datapoint.position) {
-            add_func(&mut self.grid, index, &datapoint.data);
-        }
+        let index = self.locate(datapoint.position)?;
+        add_func(&mut self.grid, index, &datapoint.data);
+        Ok(())
     }
 
-    pub fn add_data_points<S: Copy, U>(&mut self, datapoints: &[DataPoint<S>], add_func: U)
+    pub fn add_data_points<S: Copy + Debug, U>(
+        &mut self,
+        datapoints: &[DataPoint<S>],
+        add_func: U,
+    ) -> IngestReport
     where
         U: Fn(&mut Grid<T>, [usize; 2], &S),
     {
+        let mut report = IngestReport::default();
         for point in datapoints {
-            self.add_data(point, &add_func);
+            let result = self.add_data(point, &add_func);
+            report.record(&result, || format!("{:?}", point));
         }
+        report
     }
 }
 
@@ -76,24 +107,60 @@ impl<T: Copy + PartialEq + PartialOrd> HeatMap<T> {
     }
 }
 
+// Saved as a grid file with the map's bounds as its georeference, so a TempMap's accumulators can
+// be built once and reused
+impl<T: GridValue> HeatMap<T> {
+    pub fn into_grid_file(self) -> GridFile<T> {
+        GridFile::new(self.grid).with_range(self.range)
+    }
+
+    pub fn from_grid_file(file: GridFile<T>) -> Result<Self, GridFileErr> {
+        match file.range {
+            Some(range) => Ok(Self::new(file.grid, range)),
+            None => Err(GridFileErr::MissingGeoreference),
+        }
+    }
+
+    pub fn save_to_bin(&self, path: impl AsRef<Path>) -> Result<(), Box<Error>> {
+        self.save_with_metadata(path, GridMetadata::default())
+    }
+
+    pub fn save_with_metadata(
+        &self,
+        path: impl AsRef<Path>,
+        metadata: GridMetadata,
+    ) -> Result<(), Box<Error>> {
+        save_grid(path, &self.grid, Some(&self.range), &metadata)
+    }
+
+    pub fn load_from_bin(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        Ok(Self::from_grid_file(GridFile::load(path)?)?)
+    }
+}
+
 impl HeatMap<YearlyData<f32>> {
     pub fn new_temperature_grid(dimensions: (usize, usize), range: RangeBox<f32>) -> Self {
         let grid = Grid::new(dimensions.0, dimensions.1, YearlyData::new());
         HeatMap::new(grid, range)
     }
 
-    pub fn add_temperature_point(&mut self, point: &TemperaturePoint) {
+    pub fn add_temperature_point(&mut self, point: &TemperaturePoint) -> Result<(), RejectReason> {
+        if !(1..=12).contains(&point.month) {
+            return Err(RejectReason::InvalidDate);
+        }
         self.add_data(&point.data, |grid, index, data| {
             grid[index].add_to(*data, point.month);
-        });
+        })
     }
 
     pub fn average_temp_grid(&self) -> Grid<Option<f32>> {
         self.into_grid_with(|yearly_temp| yearly_temp.yearly_average())
     }
 
+    // Has no unit as squared units can't be represented
     pub fn variance_grid(&self) -> Grid<Option<f32>> {
         self.into_grid_with(|yearly_temp| yearly_temp.variance())
+            .with_unit(None)
     }
 
     pub fn standard_dev_grid(&self) -> Grid<Option<f32>> {
@@ -118,23 +185,52 @@ impl HeatMap<YearlyData<f32>> {
         range: RangeBox<f32>,
         path: impl AsRef<Path>,
     ) -> Result<Self, Box<Error>> {
+        Self::temp_heat_map_from_csv_with_report(dimensions, range, path).map(|(map, _)| map)
+    }
+
+    pub fn temp_heat_map_from_csv_with_report(
+        dimensions: (usize, usize),
+        range: RangeBox<f32>,
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, IngestReport), Box<Error>> {
+        Self::temp_heat_map_from_csv_filtered(dimensions, range, path, &IngestFilter::default())
+    }
+
+    // Rows of csvs written without a STATION column have no station, so a filter with allowed
+    // stations rejects every one of them. The csv is taken to hold TAVG in metric, as
+    // helper::_read_avg_temp writes it
+    pub fn temp_heat_map_from_csv_filtered(
+        dimensions: (usize, usize),
+        range: RangeBox<f32>,
+        path: impl AsRef<Path>,
+        filter: &IngestFilter,
+    ) -> Result<(Self, IngestReport), Box<Error>> {
         let mut reader = Reader::from_path(path)?;
-        let mut temp_grid = Self::new_temperature_grid(dimensions, range);
+        let mut temp_grid =
+            Self::new_temperature_grid(dimensions, range).with_unit(Unit::gsom_metric("TAVG"));
+        let mut report = IngestReport::default();
 
         for (i, result) in reader.deserialize().enumerate() {
             if i % 1000000 == 0 {
                 println!("{}", i);
             }
-            let record: CsvRecord = result?;
-            let point = match TemperaturePoint::from(record) {
-                Some(point) => point,
-                None => {
-                    continue;
-                }
+            let record: CsvRecord = match result {
+                Ok(record) => record,
+                Err(err) => match err.kind() {
+                    ErrorKind::Deserialize { .. } => {
+                        report.reject(RejectReason::UnparsableValue, || err.to_string());
+                        continue;
+                    }
+                    _ => return Err(Box::new(err)),
+                },
             };
-            temp_grid.add_temperature_point(&point);
+            let result = TemperaturePoint::from(&record).and_then(|point| {
+                filter.check_point(&point, record.station.as_deref(), record.elevation)?;
+                temp_grid.add_temperature_point(&point)
+            });
+            report.record(&result, || format!("{:?}", record));
         }
-        Ok(temp_grid)
+        Ok((temp_grid, report))
     }
 
     pub fn temp_heat_map_from_bin(
@@ -142,14 +238,47 @@ impl HeatMap<YearlyData<f32>> {
         range: RangeBox<f32>,
         path: impl AsRef<Path>,
     ) -> Result<Self, Box<Error>> {
-        let file = BufReader::new(File::open(path)?);
-        let values: Vec<TemperaturePoint> = deserialize_from(file)?;
-        let mut temp_grid = Self::new_temperature_grid(dimensions, range);
+        Self::temp_heat_map_from_bin_with_report(dimensions, range, path).map(|(map, _)| map)
+    }
+
+    pub fn temp_heat_map_from_bin_with_report(
+        dimensions: (usize, usize),
+        range: RangeBox<f32>,
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, IngestReport), Box<Error>> {
+        Self::temp_heat_map_from_bin_filtered(dimensions, range, path, &IngestFilter::default())
+    }
+
+    // Filtering on stations or elevations needs the StationTable saved next to the point cache
+    pub fn temp_heat_map_from_bin_filtered(
+        dimensions: (usize, usize),
+        range: RangeBox<f32>,
+        path: impl AsRef<Path>,
+        filter: &IngestFilter,
+    ) -> Result<(Self, IngestReport), Box<Error>> {
+        let stations = if filter.uses_stations() {
+            StationTable::load_from_bin(StationTable::path_for(&path))?
+        } else {
+            StationTable::new()
+        };
+        let values: PointCacheReader<TemperaturePoint> = PointCacheReader::open(path)?;
+        let mut temp_grid =
+            Self::new_temperature_grid(dimensions, range).with_unit(values.unit());
+        let mut report = IngestReport::default();
 
-        for result in values {
-            temp_grid.add_temperature_point(&result);
+        for point in values {
+            let point = point?;
+            let station = stations.station_of(&point);
+            let result = filter
+                .check_point(
+                    &point,
+                    station.map(|station| station.id.as_str()),
+                    station.and_then(|station| station.elevation),
+                )
+                .and_then(|_| temp_grid.add_temperature_point(&point));
+            report.record(&result, || format!("{:?}", point));
         }
-        Ok(temp_grid)
+        Ok((temp_grid, report))
     }
 
     pub fn into_grid_with<U: Fn(&YearlyData<f32>) -> Option<f32>>(
@@ -166,15 +295,29 @@ impl HeatMap<CSum<f32>> {
         range: RangeBox<f32>,
         path: impl AsRef<Path>,
     ) -> Result<Self, Box<Error>> {
-        let file = BufReader::new(File::open(path)?);
-        let values: Vec<DataPoint<f32>> = deserialize_from(file)?;
+        Self::elevation_map_from_bin_with_report(dimensions, range, path).map(|(map, _)| map)
+    }
+
+    pub fn elevation_map_from_bin_with_report(
+        dimensions: (usize, usize),
+        range: RangeBox<f32>,
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, IngestReport), Box<Error>> {
+        let values: PointCacheReader<DataPoint<f32>> = PointCacheReader::open(path)?;
         let grid = Grid::new(dimensions.0, dimensions.1, CSum::new());
-        let mut grid = HeatMap::new(grid, range);
+        // Station elevations are in metres, also in caches written without a unit
+        let unit = values.unit().or(Some(Unit::Metre));
+        let mut grid = HeatMap::new(grid, range).with_unit(unit);
+        let mut report = IngestReport::default();
 
-        grid.add_data_points(&values, |grid, index, &value| {
-            grid[index].add(value);
-        });
-        Ok(grid)
+        for point in values {
+            let point = point?;
+            let result = grid.add_data(&point, |grid, index, &value| {
+                grid[index].add(value);
+            });
+            report.record(&result, || format!("{:?}", point));
+        }
+        Ok((grid, report))
     }
 
     pub fn elevation_grid(&self) -> Grid<Option<f32>> {
@@ -1,30 +1,38 @@
-use bincode::serialize_into;
+use cache::PointCacheWriter;
+use colormap::Colormap;
+use cpu_render::draw_overlays;
 use csv::{Reader, WriterBuilder};
-use csv_read::read::{get_precip_stations, get_temp_stations, get_wind_stations};
+use csv_read::read::get_stations;
+use graticule::Graticule;
+use image::RgbaImage;
+use vector::VectorLayer;
 use data::{CsvRecord, TemperaturePoint, DataPoint};
 use heatmap::HeatMap;
+use ingest::{ingest, IngestOptions, IngestSummary};
 use std::error::Error;
 use std::fs::{read_dir, remove_file, File};
-use std::io::BufWriter;
 use std::path::Path;
 use math::{Range, RangeBox, Point};
 use glium::backend::glutin::Display;
 use glium::draw_parameters::DrawParameters;
 use glium::glutin::EventsLoop;
 use glium::index::{NoIndices, PrimitiveType::TrianglesList};
-use glium::texture::{CompressedSrgbTexture2d, RawImage2d};
+use glium::texture::{CompressedSrgbTexture2d, RawImage2d, SrgbTexture2d};
 use glium::{draw_parameters::Blend, Program, Surface};
 use render::{gradient_box, map_box};
+use units::Unit;
+use station::{StationMeta, StationTable};
 use window::Window;
 use input;
 use image;
 
-pub fn _read_avg_temp(_file_count: usize) {
+pub fn _read_avg_temp(input_dir: impl AsRef<Path>) {
     remove_file("Data.csv").expect("Failed to remove data file");
 
     let buffer = File::create("Data.csv").expect("Couldnt create file");
     let mut wtr = WriterBuilder::new().has_headers(false).from_writer(buffer);
     wtr.write_record(&[
+        "STATION",
         "YEAR",
         "MONTH",
         "DAY",
@@ -33,7 +41,7 @@ pub fn _read_avg_temp(_file_count: usize) {
         "ELEVATION",
         "TAVG",
     ]).expect("Failed to write headers");
-    for (i, dir) in read_dir("F:/Uni/Grand Challenges/Data/gsom-latest")
+    for (i, dir) in read_dir(input_dir)
         .unwrap()
         .enumerate()
     {
@@ -41,9 +49,19 @@ pub fn _read_avg_temp(_file_count: usize) {
             println!("{}", i);
         }
         let dir = dir.unwrap();
-        if let Ok(test) = get_temp_stations(dir.path()) {
+        if let Ok(test) = get_stations(dir.path(), &["TAVG"]) {
             for value in test {
-                wtr.serialize(value).expect("Failed");
+                let date = value.date;
+                wtr.serialize((
+                    value.station.as_deref(),
+                    date.map(|date| date.year),
+                    date.map(|date| date.month),
+                    date.and_then(|date| date.day),
+                    value.longitude,
+                    value.latitude,
+                    value.elevation,
+                    value.value("TAVG"),
+                )).expect("Failed");
             }
         } else {
             continue;
@@ -52,86 +70,55 @@ pub fn _read_avg_temp(_file_count: usize) {
     wtr.flush().expect("Flush failed");
 }
 
-pub fn read_avg_wind() -> Result<(), Box<Error>> {
-    let writer = BufWriter::new(File::create("WindData.bin")?);
-    let mut wind_vec = Vec::new();
-
-    for (i, dir) in read_dir("F:/Uni/Grand Challenges/Data/gsom-latest")?.enumerate() {
-        if i % 1000 == 1 {
-            println!("{}", i);
-        }
-        let dir = dir.unwrap();
-        if let Ok(test) = get_wind_stations(dir.path()) {
-            for value in test {
-                let point = match TemperaturePoint::from_wind(value) {
-                    Some(value) => value,
-                    None => continue,
-                };
-                wind_vec.push(point);
-            }
-        } else {
-            continue;
-        }
-    }
-    serialize_into(writer, &wind_vec)?;
-    Ok(())
+pub fn read_avg_wind(input_dir: impl AsRef<Path>) -> Result<IngestSummary, Box<Error>> {
+    ingest(&IngestOptions::new(input_dir, "AWND", "WindData.bin"))
 }
 
-pub fn read_precip() -> Result<(), Box<Error>> {
-    let writer = BufWriter::new(File::create("RainData.bin")?);
-    let mut rain_vec = Vec::new();
-
-    for (i, dir) in read_dir("F:/Uni/Grand Challenges/Data/gsom-latest")?.enumerate() {
-        if i % 1000 == 1 {
-            println!("{}", i);
-        }
-        let dir = dir.unwrap();
-        if let Ok(test) = get_precip_stations(dir.path()) {
-            for value in test {
-                let point = match TemperaturePoint::from_rain(value) {
-                    Some(value) => value,
-                    None => continue,
-                };
-                rain_vec.push(point);
-            }
-        } else {
-            continue;
-        }
-    }
-    serialize_into(writer, &rain_vec)?;
-    Ok(())
+pub fn read_precip(input_dir: impl AsRef<Path>) -> Result<IngestSummary, Box<Error>> {
+    ingest(&IngestOptions::new(input_dir, "PRCP", "RainData.bin"))
 }
 
+// The station metadata is written to StationTable::path_for("Data.b")
 pub fn csv_to_bin(path: impl AsRef<Path>) -> Result<(), Box<Error>> {
     let mut reader = Reader::from_path(path)?;
-    let mut file = File::create("Data.b")?;
+    // Data.csv holds the TAVG values of GSOM files requested in metric
+    let mut writer = PointCacheWriter::create("Data.b", Unit::gsom_metric("TAVG"))?;
+    let mut stations = StationTable::new();
 
-    let mut values = Vec::new();
     for (i, result) in reader.deserialize().enumerate() {
         if i % 1000000 == 0 {
             println!("{}", i);
         }
         let record: CsvRecord = result?;
-        let point = match TemperaturePoint::from(record) {
-            Some(point) => point,
-            None => continue,
+        let meta = StationMeta::from_csv_record(&record);
+        let point = match TemperaturePoint::from(&record) {
+            Ok(point) => point,
+            Err(_) => continue,
         };
-        values.push(point);
+        match meta {
+            Some(meta) => writer.push(&point.with_station(stations.insert(meta)))?,
+            None => writer.push(&point)?,
+        }
     }
-    serialize_into(&mut file, &values)?;
+    writer.finish()?;
+    stations.save_to_bin(StationTable::path_for("Data.b"))?;
     Ok(())
 }
 
+// The station metadata is written to StationTable::path_for("Elevation.b")
 pub fn write_elevation(path: impl AsRef<Path>) -> Result<(), Box<Error>> {
     let mut reader = Reader::from_path(path)?;
-    let mut file = BufWriter::new(File::create("Elevation.b")?);
+    let mut writer = PointCacheWriter::create("Elevation.b", Some(Unit::Metre))?;
+    let mut stations = StationTable::new();
 
-    let mut values = Vec::new();
     for (i, result) in reader.deserialize().enumerate() {
         if i % 1000000 == 0 {
             println!("{}", i);
         }
         let record: CsvRecord = result?;
+        if let Some(meta) = StationMeta::from_csv_record(&record) {
+            stations.insert(meta);
+        }
         let mut valid = true;
         let long = record.longitude.unwrap_or_else(|| {
             valid = false;
@@ -153,9 +140,10 @@ pub fn write_elevation(path: impl AsRef<Path>) -> Result<(), Box<Error>> {
             position: Point::new(long, lat),
             data: elevation
         };
-        values.push(point);
+        writer.push(&point)?;
     }
-    serialize_into(&mut file, &values)?;
+    writer.finish()?;
+    stations.save_to_bin(StationTable::path_for("Elevation.b"))?;
     Ok(())
 }
 
@@ -184,7 +172,7 @@ pub fn compare_rain_to_standard_dev(
     ).unwrap()
         .average_temp_grid();
     
-    let comparison = rain.compare_to(&variance);
+    let comparison = rain.compare_to(&variance)?;
     let file = File::create("WindVVar.csv")?;
     let mut wtr = WriterBuilder::new().has_headers(false).from_writer(file);
     wtr.write_record(&[
@@ -227,7 +215,7 @@ pub fn compare_elevation_to_deriv_sd(
     ).unwrap()
         .elevation_grid();
     
-    let comparison = elevation.compare_to(&variance);
+    let comparison = elevation.compare_to(&variance)?;
     let file = File::create("ElevationVSDD.csv")?;
     let mut wtr = WriterBuilder::new().has_headers(false).from_writer(file);
     wtr.write_record(&[
@@ -347,3 +335,116 @@ pub fn compare_var_to_rain() {
     }
 }
 
+
+// Opens a window showing the heat map over the world map, coloured with the colormap. The
+// colorbar on the right uses the same palette. The vectors and graticule are drawn over the map
+pub fn show_heat_map(
+    heat_map: &HeatMap<Option<f32>>,
+    colormap: &Colormap,
+    value_range: Option<Range<f32>>,
+    vectors: &[VectorLayer],
+    graticule: Option<&Graticule>,
+) {
+    let mut events_loop = EventsLoop::new();
+    let mut window = Window::new(false, true, true, [1800.0, 900.0], &events_loop);
+    let program = Program::from_source(
+        &window.display,
+        include_str!("shaders/vertex.glsl"),
+        include_str!("shaders/colormap.glsl"),
+        None,
+    ).unwrap();
+    let grad_program = Program::from_source(
+        &window.display,
+        include_str!("shaders/vertex.glsl"),
+        include_str!("shaders/colormap_gradient.glsl"),
+        None,
+    ).unwrap();
+    let overlay_program = Program::from_source(
+        &window.display,
+        include_str!("shaders/vertex.glsl"),
+        include_str!("shaders/overlay.glsl"),
+        None,
+    ).unwrap();
+    let buffer = map_box(&window.display);
+    let grad_buffer = gradient_box(&window.display);
+
+    let range = &heat_map.range;
+    // The map box covers 0.95 of the window's width, see render::map_box
+    let overlay = if vectors.is_empty() && graticule.is_none() {
+        None
+    } else {
+        let (width, height) = window.display.get_framebuffer_dimensions();
+        let mut image = RgbaImage::new((width as f32 * 0.95) as u32, height);
+        draw_overlays(&mut image, range, vectors, graticule);
+        let dims = image.dimensions();
+        let raw = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dims);
+        Some(SrgbTexture2d::new(&window.display, raw).expect("Failed to create texture"))
+    };
+    let min_pos = [range.horizontal.from, range.vertical.from];
+    let max_pos = [range.horizontal.to, range.vertical.to];
+    let (texture, _) = heat_map
+        .grid
+        .into_normalised_texture(&window.display, value_range);
+    let palette = colormap.texture(&window.display);
+    let map_texture = load_image(&window.display, "Pure B and W Map.png");
+    let mut contrast = 0.0;
+    let draw_parameters = DrawParameters {
+        blend: Blend::alpha_blending(),
+        ..Default::default()
+    };
+
+    while window.open {
+        events_loop.poll_events(|event| {
+            window.closer(&event);
+            input::take_input(&event, &mut contrast)
+        });
+        let uniforms = uniform! {
+            map: &texture,
+            bwmap: &map_texture,
+            palette: &palette,
+            under_colour: colormap.under_colour(),
+            over_colour: colormap.over_colour(),
+            nodata_colour: colormap.nodata,
+            min_pos: min_pos,
+            max_pos: max_pos,
+            contrast: contrast
+        };
+        let mut target = window.display.draw();
+        target.clear_color(0.0, 0.0, 0.0, 0.0);
+        target
+            .draw(
+                &buffer,
+                NoIndices(TrianglesList),
+                &program,
+                &uniforms,
+                &draw_parameters,
+            )
+            .unwrap();
+        if let Some(ref overlay) = overlay {
+            target
+                .draw(
+                    &buffer,
+                    NoIndices(TrianglesList),
+                    &overlay_program,
+                    &uniform! { overlay: overlay },
+                    &draw_parameters,
+                )
+                .unwrap();
+        }
+        let uniforms = uniform! {
+            palette: &palette,
+            contrast: contrast
+        };
+
+        target
+            .draw(
+                &grad_buffer,
+                NoIndices(TrianglesList),
+                &grad_program,
+                &uniforms,
+                &draw_parameters,
+            )
+            .unwrap();
+        target.finish().unwrap();
+    }
+}
@@ -0,0 +1,486 @@
+use cache::PointCacheWriter;
+use csv_read::errors::HeaderContainerErr;
+use csv_read::read::{stream_schema_stations, ElementAttributes, StationRecord};
+use csv_read::schema::{Field, Schema};
+use data::TemperaturePoint;
+use math::{Point, RangeBox};
+use rayon::prelude::*;
+use report::{IngestReport, RejectReason};
+use station::{StationMeta, StationTable};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use units::{Unit, UnitErr};
+
+const FILES_PER_BATCH: usize = 256;
+
+#[derive(Clone, Debug)]
+pub enum FileSelection {
+    // Every file in the input directory
+    All,
+    // Files in the input directory whose name matches the pattern, supports '*' and '?'
+    Glob(String),
+    // Explicit list of files, relative paths are taken from the input directory
+    Files(Vec<PathBuf>),
+}
+
+// Rejects values based on their GSOM attribute flags. Values without attributes are kept
+#[derive(Clone, Debug, Default)]
+pub struct QualityFilter {
+    // Eg. 'D' (duplicate), 'I' (inconsistency), 'S' (spatial), 'X' (failed bounds check)
+    pub rejected_quality_flags: Vec<char>,
+    pub rejected_measurement_flags: Vec<char>,
+    pub max_missing_days: Option<u32>,
+}
+
+impl QualityFilter {
+    // Rejects any value that has a quality flag, ie. anything that failed a QC check
+    pub fn reject_failed_qc() -> Self {
+        Self {
+            rejected_quality_flags: "DGIKLMNORSTWXZ".chars().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_max_missing_days(mut self, max_missing_days: u32) -> Self {
+        self.max_missing_days = Some(max_missing_days);
+        self
+    }
+
+    pub fn accepts(&self, attributes: Option<ElementAttributes>) -> bool {
+        let attributes = match attributes {
+            Some(attributes) => attributes,
+            None => return true,
+        };
+        if let Some(flag) = attributes.quality {
+            if self.rejected_quality_flags.contains(&flag) {
+                return false;
+            }
+        }
+        if let Some(flag) = attributes.measurement {
+            if self.rejected_measurement_flags.contains(&flag) {
+                return false;
+            }
+        }
+        match (self.max_missing_days, attributes.missing_days) {
+            (Some(max), Some(missing)) => missing <= max,
+            _ => true,
+        }
+    }
+
+    // Attributes that couldn't be parsed can't be checked, so only a filter that checks nothing
+    // accepts their values
+    pub fn accepts_unparsable(&self) -> bool {
+        self.rejected_quality_flags.is_empty()
+            && self.rejected_measurement_flags.is_empty()
+            && self.max_missing_days.is_none()
+    }
+}
+
+// Selects the records that are ingested, eg. the years of a climate normal or a regional
+// subset. Every criterion is optional. Records missing the information a set criterion needs,
+// such as an elevation band for a station without an elevation, are rejected
+#[derive(Clone, Debug, Default)]
+pub struct IngestFilter {
+    // Inclusive
+    pub years: Option<(u32, u32)>,
+    pub months: Option<Vec<u32>>,
+    // Includes the edges, unlike RangeBox::contains
+    pub region: Option<RangeBox<f32>>,
+    // Inclusive, in metres
+    pub elevation: Option<(f32, f32)>,
+    // Only these stations are kept when set
+    pub allowed_stations: Option<HashSet<String>>,
+    pub denied_stations: HashSet<String>,
+}
+
+impl IngestFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_years(mut self, from: u32, to: u32) -> Self {
+        self.years = Some((from, to));
+        self
+    }
+
+    pub fn with_months(mut self, months: &[u32]) -> Self {
+        self.months = Some(months.to_vec());
+        self
+    }
+
+    pub fn with_region(mut self, region: RangeBox<f32>) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn with_elevation(mut self, min: f32, max: f32) -> Self {
+        self.elevation = Some((min, max));
+        self
+    }
+
+    pub fn with_allowed_stations(mut self, stations: &[&str]) -> Self {
+        let allowed = self.allowed_stations.get_or_insert_with(HashSet::new);
+        allowed.extend(stations.iter().map(|&station| String::from(station)));
+        self
+    }
+
+    pub fn with_denied_stations(mut self, stations: &[&str]) -> Self {
+        let denied = &mut self.denied_stations;
+        denied.extend(stations.iter().map(|&station| String::from(station)));
+        self
+    }
+
+    // Whether the filter needs station ids or elevations, which point caches only have through
+    // their StationTable
+    pub fn uses_stations(&self) -> bool {
+        self.elevation.is_some()
+            || self.allowed_stations.is_some()
+            || !self.denied_stations.is_empty()
+    }
+
+    pub fn check_date(&self, year: Option<u32>, month: Option<u32>) -> Result<(), RejectReason> {
+        if let Some((from, to)) = self.years {
+            match year {
+                Some(year) if year >= from && year <= to => (),
+                _ => return Err(RejectReason::OutsideYears),
+            }
+        }
+        if let Some(ref months) = self.months {
+            match month {
+                Some(month) if months.contains(&month) => (),
+                _ => return Err(RejectReason::ExcludedMonth),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_position(&self, position: Option<Point<f32>>) -> Result<(), RejectReason> {
+        if let Some(ref region) = self.region {
+            match position {
+                Some(point)
+                    if point.x >= region.horizontal.from
+                        && point.x <= region.horizontal.to
+                        && point.y >= region.vertical.from
+                        && point.y <= region.vertical.to => {}
+                _ => return Err(RejectReason::OutsideRegion),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_elevation(&self, elevation: Option<f32>) -> Result<(), RejectReason> {
+        if let Some((min, max)) = self.elevation {
+            match elevation {
+                Some(elevation) if elevation >= min && elevation <= max => (),
+                _ => return Err(RejectReason::OutsideElevation),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_station(&self, station: Option<&str>) -> Result<(), RejectReason> {
+        if let Some(ref allowed) = self.allowed_stations {
+            match station {
+                Some(station) if allowed.contains(station) => (),
+                _ => return Err(RejectReason::ExcludedStation),
+            }
+        }
+        match station {
+            Some(station) if self.denied_stations.contains(station) => {
+                Err(RejectReason::ExcludedStation)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_record(&self, record: &StationRecord) -> Result<(), RejectReason> {
+        let position = match (record.longitude, record.latitude) {
+            (Some(long), Some(lat)) => Some(Point::new(long, lat)),
+            _ => None,
+        };
+        self.check_date(
+            record.date.map(|date| date.year),
+            record.date.map(|date| date.month),
+        )?;
+        self.check_position(position)?;
+        self.check_elevation(record.elevation)?;
+        self.check_station(record.station.as_deref())
+    }
+
+    // Points don't carry their station's id or elevation, these come from the StationTable or the
+    // csv row the point was read from
+    pub fn check_point(
+        &self,
+        point: &TemperaturePoint,
+        station: Option<&str>,
+        elevation: Option<f32>,
+    ) -> Result<(), RejectReason> {
+        self.check_date(point.year, Some(point.month as u32))?;
+        self.check_position(Some(point.data.position))?;
+        self.check_elevation(elevation)?;
+        self.check_station(station)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IngestOptions {
+    pub input_dir: PathBuf,
+    pub files: FileSelection,
+    // Columns to read, the GSOM schema of an element code such as "TAVG", "AWND" or "PRCP"
+    // unless another is given
+    pub schema: Schema,
+    pub output: PathBuf,
+    pub quality: QualityFilter,
+    // Values are converted from the schema's value unit to this unit
+    pub convert_to: Option<Unit>,
+    pub filter: IngestFilter,
+}
+
+impl IngestOptions {
+    pub fn new(
+        input_dir: impl AsRef<Path>,
+        element: &str,
+        output: impl AsRef<Path>,
+    ) -> Self {
+        Self {
+            input_dir: input_dir.as_ref().to_path_buf(),
+            files: FileSelection::All,
+            schema: Schema::gsom(element),
+            output: output.as_ref().to_path_buf(),
+            quality: QualityFilter::default(),
+            convert_to: None,
+            filter: IngestFilter::default(),
+        }
+    }
+
+    pub fn with_files(mut self, files: FileSelection) -> Self {
+        self.files = files;
+        self
+    }
+
+    pub fn with_quality(mut self, quality: QualityFilter) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: IngestFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_conversion(mut self, unit: Unit) -> Self {
+        self.convert_to = Some(unit);
+        self
+    }
+
+    // Unit of the values written to the output
+    pub fn output_unit(&self) -> Option<Unit> {
+        self.convert_to.or_else(|| self.schema.unit(Field::Value))
+    }
+
+    pub fn selected_files(&self) -> Result<Vec<PathBuf>, Box<Error>> {
+        let mut paths = Vec::new();
+        match self.files {
+            FileSelection::Files(ref files) => {
+                for file in files {
+                    paths.push(self.input_dir.join(file));
+                }
+            }
+            FileSelection::All | FileSelection::Glob(_) => {
+                for entry in read_dir(&self.input_dir)? {
+                    let path = entry?.path();
+                    if !path.is_file() {
+                        continue;
+                    }
+                    if let FileSelection::Glob(ref pattern) = self.files {
+                        let name = match path.file_name().and_then(|name| name.to_str()) {
+                            Some(name) => name,
+                            None => continue,
+                        };
+                        if !wildcard_match(pattern, name) {
+                            continue;
+                        }
+                    }
+                    paths.push(path);
+                }
+                paths.sort();
+            }
+        }
+        Ok(paths)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IngestSummary {
+    pub files_read: usize,
+    // Files that did not contain the requested element
+    pub files_skipped: usize,
+    // Files that could not be opened or parsed
+    pub files_failed: Vec<(PathBuf, String)>,
+    pub points_written: usize,
+    pub stations: usize,
+    pub unit: Option<Unit>,
+    pub report: IngestReport,
+}
+
+enum FileOutcome {
+    // Points hold indices into the file's own station table
+    Read(StationTable, Vec<TemperaturePoint>, IngestReport),
+    Skipped,
+    Failed(String),
+}
+
+fn read_file(path: &Path, options: &IngestOptions) -> FileOutcome {
+    let schema = &options.schema;
+    let quality = &options.quality;
+    let element = schema.element.as_str();
+    let conversion = match (schema.unit(Field::Value), options.convert_to) {
+        (Some(from), Some(to)) => Some((from, to)),
+        _ => None,
+    };
+    let convert = if Unit::gsom_is_interval(element) {
+        Unit::convert_interval
+    } else {
+        Unit::convert
+    };
+    match stream_schema_stations(path, schema) {
+        Ok(stations) => {
+            let mut table = StationTable::new();
+            let mut report = IngestReport::default();
+            let mut points = Vec::new();
+            for station in stations {
+                let mut station = match station {
+                    Ok(Ok(station)) => station,
+                    Ok(Err(rejected)) => {
+                        report.reject(rejected.reason, || {
+                            format!("{}: {:?}", path.display(), rejected.record)
+                        });
+                        continue;
+                    }
+                    Err(err) => return FileOutcome::Failed(err.to_string()),
+                };
+                let had_value = station.value(element).is_some();
+                let unchecked = station.unparsable_attributes.clone();
+                station.retain_values(|name, attributes| {
+                    let parsed = !unchecked.iter().any(|unchecked| unchecked == name);
+                    quality.accepts(attributes) && (parsed || quality.accepts_unparsable())
+                });
+                let result = if had_value && station.value(element).is_none() {
+                    Err(RejectReason::FailedQuality)
+                } else {
+                    TemperaturePoint::from_station(&station, element)
+                        .and_then(|point| options.filter.check_record(&station).map(|_| point))
+                };
+                report.record(&result, || format!("{}: {:?}", path.display(), station));
+                let mut point = match result {
+                    Ok(point) => point,
+                    Err(_) => continue,
+                };
+                if let Some((from, to)) = conversion {
+                    // Checked to be compatible before reading any files
+                    let value = point.data.data;
+                    point.data.data = convert(&from, value, to).unwrap_or(value);
+                }
+                match StationMeta::from_record(&station) {
+                    Some(meta) => points.push(point.with_station(table.insert(meta))),
+                    None => points.push(point),
+                }
+            }
+            FileOutcome::Read(table, points, report)
+        }
+        Err(err) => {
+            if err.downcast_ref::<HeaderContainerErr>().is_some() {
+                FileOutcome::Skipped
+            } else {
+                FileOutcome::Failed(err.to_string())
+            }
+        }
+    }
+}
+
+// Reads the selected station files in parallel and writes every point to the output as a point
+// cache of TemperaturePoints, which can be loaded with HeatMap::temp_heat_map_from_bin.
+// The station metadata is written to StationTable::path_for(output)
+pub fn ingest(options: &IngestOptions) -> Result<IngestSummary, Box<Error>> {
+    if let Some(to) = options.convert_to {
+        match options.schema.unit(Field::Value) {
+            Some(from) => from.check_compatible(to)?,
+            None => return Err(Box::new(UnitErr::Missing)),
+        }
+    }
+    let files = options.selected_files()?;
+    let mut summary = IngestSummary {
+        unit: options.output_unit(),
+        ..Default::default()
+    };
+    let mut stations = StationTable::new();
+    let mut writer = PointCacheWriter::create(&options.output, options.output_unit())?;
+
+    // Files are read in batches so only one batch of points is held in memory at a time
+    for batch in files.chunks(FILES_PER_BATCH) {
+        let outcomes: Vec<(&PathBuf, FileOutcome)> = batch
+            .par_iter()
+            .map(|path| (path, read_file(path, options)))
+            .collect();
+
+        for (path, outcome) in outcomes {
+            match outcome {
+                FileOutcome::Read(file_stations, values, report) => {
+                    summary.files_read += 1;
+                    summary.report.merge(report);
+                    let indices = stations.merge(file_stations);
+                    for mut point in values {
+                        point.station = point.station.map(|index| indices[index as usize]);
+                        writer.push(&point)?;
+                    }
+                }
+                FileOutcome::Skipped => summary.files_skipped += 1,
+                FileOutcome::Failed(err) => summary.files_failed.push((path.clone(), err)),
+            }
+        }
+    }
+
+    summary.points_written = writer.finish()? as usize;
+    stations.save_to_bin(StationTable::path_for(&options.output))?;
+    summary.stations = stations.len();
+    Ok(summary)
+}
+
+// Matches a file name against a pattern where '*' is any run of characters and '?' is any
+// single character
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let mut p = 0;
+    let mut n = 0;
+    let mut star = None;
+    let mut star_n = 0;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some(p);
+            star_n = n;
+            p += 1;
+        } else if let Some(star_p) = star {
+            p = star_p + 1;
+            star_n += 1;
+            n = star_n;
+        } else {
+            return false;
+        }
+    }
+    while p < pattern.len() && pattern[p] == '*' {
+        p += 1;
+    }
+    p == pattern.len()
+}
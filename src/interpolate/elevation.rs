@@ -0,0 +1,262 @@
+use cache::PointCacheReader;
+use data::DataPoint;
+use grid::Grid;
+use heatmap::HeatMap;
+use interpolate::linear::solve;
+use interpolate::neighbours::{Distance, NeighbourIndex};
+use interpolate::{cell_position, station_means, Interpolator};
+use math::Point;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use units::{Unit, UnitErr};
+
+// Metres of elevation spread a neighbourhood needs before its own lapse rate counts for as much
+// as the one over all stations. Flat neighbourhoods say little about the lapse rate
+const PRIOR_SPREAD: f64 = 200.0;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ElevatedValue {
+    pub value: f32,
+    // In metres
+    pub elevation: f32,
+}
+
+// Pairs station values with the station elevations at the same position. Stations without an
+// elevation are left out
+pub fn pair_with_elevations(
+    samples: &[DataPoint<f32>],
+    elevations: &[DataPoint<f32>],
+) -> Vec<DataPoint<ElevatedValue>> {
+    let elevations: HashMap<(u32, u32), f32> = elevations
+        .iter()
+        .map(|point| {
+            (
+                (point.position.x.to_bits(), point.position.y.to_bits()),
+                point.data,
+            )
+        })
+        .collect();
+    samples
+        .iter()
+        .filter_map(|sample| {
+            let key = (sample.position.x.to_bits(), sample.position.y.to_bits());
+            elevations.get(&key).map(|&elevation| {
+                DataPoint::new(
+                    sample.position,
+                    ElevatedValue {
+                        value: sample.data,
+                        elevation,
+                    },
+                )
+            })
+        })
+        .collect()
+}
+
+// Station elevations from a point cache written by write_elevation, which has a point for every
+// record of a station so they are averaged
+pub fn load_elevations(path: impl AsRef<Path>) -> Result<Vec<DataPoint<f32>>, Box<Error>> {
+    let values: PointCacheReader<DataPoint<f32>> = PointCacheReader::open(path)?;
+    let mut points = Vec::new();
+    for point in values {
+        points.push(point?);
+    }
+    Ok(station_means(points))
+}
+
+// Which stations the lapse rate is fitted to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LapseRegion {
+    // One lapse rate for everywhere, fitted to every station
+    Global,
+    // A lapse rate for each position, fitted to this many of the nearest stations weighted
+    // towards the closest. Pulled towards the global one where they are at similar elevations
+    Local(usize),
+}
+
+// Terms of the regression, 1, longitude, latitude and elevation
+const TERMS: usize = 4;
+
+// Weighted least squares fit of value against elevation alongside a plane in longitude and
+// latitude, so a gradient across the map that happens to follow the terrain isn't taken for
+// the effect of elevation
+struct Regression {
+    origin: Point<f32>,
+    distance: Distance,
+    // Normal equations, built up a sample at a time
+    normal: [f64; TERMS * TERMS],
+    rhs: [f64; TERMS],
+}
+
+impl Regression {
+    fn new(origin: Point<f32>, distance: Distance) -> Self {
+        Self {
+            origin,
+            distance,
+            normal: [0.0; TERMS * TERMS],
+            rhs: [0.0; TERMS],
+        }
+    }
+
+    fn add(&mut self, sample: &DataPoint<ElevatedValue>, weight: f64) {
+        let mut dx = f64::from(sample.position.x - self.origin.x);
+        if self.distance == Distance::GreatCircle {
+            // Longitudes either side of the antimeridian are close together
+            dx = (dx + 180.0).rem_euclid(360.0) - 180.0;
+        }
+        let dy = f64::from(sample.position.y - self.origin.y);
+        let terms = [1.0, dx, dy, f64::from(sample.data.elevation)];
+        for row in 0..TERMS {
+            for column in 0..TERMS {
+                self.normal[row * TERMS + column] += weight * terms[row] * terms[column];
+            }
+            self.rhs[row] += weight * terms[row] * f64::from(sample.data.value);
+        }
+    }
+
+    // Change in value per metre. The prior slope counts for as much as a spread of
+    // PRIOR_SPREAD metres over the same total weight. Without a prior, samples that can't
+    // separate elevation from position give no slope
+    fn slope(&self, prior: Option<f64>) -> f64 {
+        let mut normal = self.normal.to_vec();
+        let mut rhs = self.rhs.to_vec();
+        if let Some(prior) = prior {
+            let strength = self.normal[0] * PRIOR_SPREAD * PRIOR_SPREAD;
+            normal[TERMS * TERMS - 1] += strength;
+            rhs[TERMS - 1] += strength * prior;
+        }
+        solve(normal, rhs).map_or(prior.unwrap_or(0.0), |solution| solution[TERMS - 1])
+    }
+}
+
+// Interpolates values that depend on elevation, such as temperatures. Each station value is
+// split into the share its elevation explains, through the lapse rate, and a residual. The
+// residuals vary smoothly enough to interpolate, and the elevation's share is added back from
+// an elevation grid so mountains show up at the grid's resolution rather than the stations'
+pub struct ElevationAdjusted<I: Interpolator> {
+    index: NeighbourIndex<ElevatedValue>,
+    region: LapseRegion,
+    // Change in value per metre over every station
+    global_lapse: f64,
+    residuals: I,
+}
+
+impl<I: Interpolator> ElevationAdjusted<I> {
+    // The residuals are interpolated with whatever the interpolator function builds from them,
+    // for example |residuals| Idw::new(residuals, Distance::GreatCircle)
+    pub fn new<F>(
+        samples: Vec<DataPoint<ElevatedValue>>,
+        distance: Distance,
+        region: LapseRegion,
+        interpolator: F,
+    ) -> Self
+    where
+        F: FnOnce(Vec<DataPoint<f32>>) -> I,
+    {
+        let count = samples.len().max(1) as f32;
+        let centre = samples.iter().fold(Point::new(0.0, 0.0), |sum, sample| {
+            Point::new(
+                sum.x + sample.position.x / count,
+                sum.y + sample.position.y / count,
+            )
+        });
+        let mut regression = Regression::new(centre, distance);
+        for sample in &samples {
+            regression.add(sample, 1.0);
+        }
+        let global_lapse = regression.slope(None);
+        let index = NeighbourIndex::new(samples, distance);
+        let residuals = index
+            .samples()
+            .iter()
+            .map(|sample| {
+                let lapse = Self::lapse_from(&index, region, global_lapse, sample.position);
+                let residual =
+                    f64::from(sample.data.value) - lapse * f64::from(sample.data.elevation);
+                DataPoint::new(sample.position, residual as f32)
+            })
+            .collect();
+        Self {
+            index,
+            region,
+            global_lapse,
+            residuals: interpolator(residuals),
+        }
+    }
+
+    fn lapse_from(
+        index: &NeighbourIndex<ElevatedValue>,
+        region: LapseRegion,
+        global_lapse: f64,
+        position: Point<f32>,
+    ) -> f64 {
+        let count = match region {
+            LapseRegion::Global => return global_lapse,
+            LapseRegion::Local(count) => count.max(1),
+        };
+        // One more than wanted, the furthest sets the bandwidth and so gets no weight, which
+        // keeps the lapse rate continuous as stations enter and leave the neighbourhood
+        let neighbours = index.nearest(position, Some(count + 1), None);
+        let bandwidth = neighbours.last().map_or(0.0, |&(_, distance)| distance);
+        let samples = index.samples();
+        let mut regression = Regression::new(position, index.distance());
+        for &(sample, distance) in &neighbours {
+            let weight = if bandwidth > 0.0 {
+                (1.0 - (f64::from(distance) / f64::from(bandwidth)).powi(2)).powi(2)
+            } else {
+                1.0
+            };
+            regression.add(&samples[sample], weight);
+        }
+        regression.slope(Some(global_lapse))
+    }
+
+    pub fn samples(&self) -> &[DataPoint<ElevatedValue>] {
+        self.index.samples()
+    }
+
+    // Change in value per kilometre of elevation over every station
+    pub fn global_lapse_rate(&self) -> f32 {
+        (self.global_lapse * 1000.0) as f32
+    }
+
+    // Change in value per kilometre of elevation used at the position
+    pub fn lapse_rate(&self, position: Point<f32>) -> f32 {
+        let lapse = Self::lapse_from(&self.index, self.region, self.global_lapse, position);
+        (lapse * 1000.0) as f32
+    }
+
+    // Estimate at a position of the given elevation in metres
+    pub fn estimate(&self, position: Point<f32>, elevation: f32) -> Option<f32> {
+        let residual = self.residuals.estimate(position)?;
+        let lapse = Self::lapse_from(&self.index, self.region, self.global_lapse, position);
+        Some((f64::from(residual) + lapse * f64::from(elevation)) as f32)
+    }
+
+    // Estimates every cell of the elevation map at its elevation, so the result has the map's
+    // size and range. Cells without an elevation are left as None. Elevations without a unit
+    // are taken to be in metres, ones in another unit are converted
+    pub fn grid(&self, elevation: &HeatMap<Option<f32>>) -> Result<Grid<Option<f32>>, UnitErr> {
+        let metres = match elevation.unit() {
+            Some(_) => elevation.grid.convert_to(Unit::Metre)?,
+            None => elevation.grid.clone(),
+        };
+        let dimensions = (metres.horizontal, metres.vertical);
+        let values = metres
+            .values
+            .par_iter()
+            .enumerate()
+            .map(|(index, &height)| {
+                let position = cell_position(
+                    [index % dimensions.0, index / dimensions.0],
+                    dimensions,
+                    &elevation.range,
+                );
+                self.estimate(position, height?)
+            })
+            .collect();
+        Ok(Grid::new_from_values(dimensions.0, dimensions.1, values))
+    }
+}
@@ -0,0 +1,84 @@
+use data::DataPoint;
+use grid::Grid;
+use interpolate::neighbours::{Distance, NeighbourIndex};
+use interpolate::{interpolate_grid, Interpolator};
+use math::{Point, RangeBox};
+
+// Distances below this count as being on the sample, in the units of the distance
+const COINCIDENT: f32 = 1e-6;
+
+// Inverse distance weighting, each estimate is the mean of the nearby samples weighted by
+// 1 / distance ^ power. Higher powers favour the closest samples more
+pub struct Idw {
+    index: NeighbourIndex,
+    pub power: f32,
+    // In degrees for planar distances and kilometres for great circle ones
+    pub radius: Option<f32>,
+    pub max_neighbours: Option<usize>,
+    // Cells with fewer samples in reach are left as None
+    pub min_neighbours: usize,
+}
+
+impl Idw {
+    pub fn new(samples: Vec<DataPoint<f32>>, distance: Distance) -> Self {
+        Self {
+            index: NeighbourIndex::new(samples, distance),
+            power: 2.0,
+            radius: None,
+            max_neighbours: Some(12),
+            min_neighbours: 1,
+        }
+    }
+
+    pub fn with_power(mut self, power: f32) -> Self {
+        self.power = power;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+
+    // None uses every sample within the radius
+    pub fn with_max_neighbours(mut self, max_neighbours: Option<usize>) -> Self {
+        self.max_neighbours = max_neighbours;
+        self
+    }
+
+    pub fn with_min_neighbours(mut self, min_neighbours: usize) -> Self {
+        self.min_neighbours = min_neighbours.max(1);
+        self
+    }
+
+    pub fn samples(&self) -> &[DataPoint<f32>] {
+        self.index.samples()
+    }
+
+    pub fn grid(&self, dimensions: (usize, usize), range: &RangeBox<f32>) -> Grid<Option<f32>> {
+        interpolate_grid(self, dimensions, range)
+    }
+}
+
+impl Interpolator for Idw {
+    fn estimate(&self, position: Point<f32>) -> Option<f32> {
+        let neighbours = self
+            .index
+            .nearest(position, self.max_neighbours, self.radius);
+        if neighbours.len() < self.min_neighbours {
+            return None;
+        }
+        let samples = self.index.samples();
+        let (mut weighted, mut weights) = (0f64, 0f64);
+        for (index, distance) in neighbours {
+            // A sample on the position is the estimate
+            if distance < COINCIDENT {
+                return Some(samples[index].data);
+            }
+            let weight = 1.0 / f64::from(distance).powf(f64::from(self.power));
+            weighted += weight * f64::from(samples[index].data);
+            weights += weight;
+        }
+        Some((weighted / weights) as f32)
+    }
+}
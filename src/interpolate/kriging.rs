@@ -0,0 +1,148 @@
+use data::DataPoint;
+use grid::Grid;
+use interpolate::linear::solve;
+use interpolate::neighbours::{Distance, NeighbourIndex};
+use interpolate::variogram::{EmpiricalVariogram, VariogramModel};
+use interpolate::{cell_position, Interpolator};
+use math::{Point, RangeBox};
+use rayon::prelude::*;
+
+// Bins of the empirical variogram when the model is fitted automatically
+const VARIOGRAM_BINS: usize = 20;
+
+// Ordinary kriging over local neighbourhoods. Each estimate solves for the weights of its
+// nearest samples that give the lowest variance under the variogram model, with the weights
+// summing to 1 so the unknown local mean drops out
+pub struct Kriging {
+    index: NeighbourIndex,
+    pub model: VariogramModel,
+    pub max_neighbours: usize,
+    // In the units of the distance, samples further away aren't used
+    pub radius: Option<f32>,
+    // Cells with fewer samples in reach are left as None
+    pub min_neighbours: usize,
+}
+
+impl Kriging {
+    pub fn new(samples: Vec<DataPoint<f32>>, distance: Distance, model: VariogramModel) -> Self {
+        Self {
+            index: NeighbourIndex::new(samples, distance),
+            model,
+            max_neighbours: 16,
+            radius: None,
+            min_neighbours: 3,
+        }
+    }
+
+    // Fits every kind of model to the empirical variogram of the samples and uses the best.
+    // None when the samples are too few or too close together to give a variogram
+    pub fn fitted(samples: Vec<DataPoint<f32>>, distance: Distance) -> Option<Self> {
+        let variogram = EmpiricalVariogram::from_samples(&samples, distance, VARIOGRAM_BINS);
+        let model = VariogramModel::fit_best(&variogram)?;
+        Some(Self::new(samples, distance, model))
+    }
+
+    pub fn with_max_neighbours(mut self, max_neighbours: usize) -> Self {
+        self.max_neighbours = max_neighbours.max(1);
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+
+    pub fn with_min_neighbours(mut self, min_neighbours: usize) -> Self {
+        self.min_neighbours = min_neighbours.max(1);
+        self
+    }
+
+    pub fn samples(&self) -> &[DataPoint<f32>] {
+        self.index.samples()
+    }
+
+    // Estimate and kriging variance at the position
+    pub fn estimate_with_variance(&self, position: Point<f32>) -> Option<(f32, f32)> {
+        let neighbours = self
+            .index
+            .nearest(position, Some(self.max_neighbours), self.radius);
+        if neighbours.is_empty() || neighbours.len() < self.min_neighbours {
+            return None;
+        }
+        let samples = self.index.samples();
+        let distance = self.index.distance();
+        let n = neighbours.len();
+        // A model without a sill, as fitted to a constant field, makes every semivariance 0 and
+        // the system singular. Every sample is then as good as any other
+        if self.model.sill() <= 0.0 {
+            let sum: f64 = neighbours
+                .iter()
+                .map(|&(index, _)| f64::from(samples[index].data))
+                .sum();
+            return Some(((sum / n as f64) as f32, 0.0));
+        }
+        let size = n + 1;
+        // Semivariances between the samples, bordered by the constraint that weights sum to 1
+        let mut matrix = vec![0f64; size * size];
+        for (row, &(first, _)) in neighbours.iter().enumerate() {
+            for (column, &(second, _)) in neighbours.iter().enumerate().skip(row + 1) {
+                let lag = distance.between(samples[first].position, samples[second].position);
+                let value = f64::from(self.model.value(lag));
+                matrix[row * size + column] = value;
+                matrix[column * size + row] = value;
+            }
+            matrix[row * size + n] = 1.0;
+            matrix[n * size + row] = 1.0;
+        }
+        let mut rhs: Vec<f64> = neighbours
+            .iter()
+            .map(|&(_, lag)| f64::from(self.model.value(lag)))
+            .collect();
+        rhs.push(1.0);
+        let solution = solve(matrix, rhs.clone())?;
+        let estimate: f64 = neighbours
+            .iter()
+            .zip(&solution)
+            .map(|(&(index, _), weight)| weight * f64::from(samples[index].data))
+            .sum();
+        // Sum of weight * semivariance to the position, plus the Lagrange multiplier which is
+        // the last unknown with a 1 on the right hand side
+        let variance: f64 = solution.iter().zip(&rhs).map(|(a, b)| a * b).sum();
+        Some((estimate as f32, variance.max(0.0) as f32))
+    }
+
+    // Estimate and kriging variance grids of the given size covering the range, computed in
+    // parallel. The variance is in the square of the samples' unit
+    pub fn grids(
+        &self,
+        dimensions: (usize, usize),
+        range: &RangeBox<f32>,
+    ) -> (Grid<Option<f32>>, Grid<Option<f32>>) {
+        let (estimates, variances): (Vec<Option<f32>>, Vec<Option<f32>>) = (0..dimensions.0
+            * dimensions.1)
+            .into_par_iter()
+            .map(|index| {
+                let position = cell_position(
+                    [index % dimensions.0, index / dimensions.0],
+                    dimensions,
+                    range,
+                );
+                match self.estimate_with_variance(position) {
+                    Some((estimate, variance)) => (Some(estimate), Some(variance)),
+                    None => (None, None),
+                }
+            })
+            .unzip();
+        (
+            Grid::new_from_values(dimensions.0, dimensions.1, estimates),
+            Grid::new_from_values(dimensions.0, dimensions.1, variances),
+        )
+    }
+}
+
+impl Interpolator for Kriging {
+    fn estimate(&self, position: Point<f32>) -> Option<f32> {
+        self.estimate_with_variance(position)
+            .map(|(estimate, _)| estimate)
+    }
+}
@@ -0,0 +1,56 @@
+// Dense linear systems for the interpolators that solve for weights
+
+// Pivots smaller than this are taken to mean the matrix is singular
+const SINGULAR: f64 = 1e-12;
+
+// Solves matrix * x = rhs by Gaussian elimination with partial pivoting. The matrix is n by n
+// and stored a row at a time. None when the system is singular
+pub fn solve(mut matrix: Vec<f64>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let n = rhs.len();
+    if matrix.len() != n * n {
+        return None;
+    }
+    // Scale of the matrix, so the singular check doesn't depend on the units of the values
+    let scale = matrix.iter().fold(0f64, |max, value| max.max(value.abs()));
+    if scale == 0.0 {
+        return None;
+    }
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&first, &second| {
+                matrix[first * n + column]
+                    .abs()
+                    .partial_cmp(&matrix[second * n + column].abs())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        if matrix[pivot * n + column].abs() <= SINGULAR * scale {
+            return None;
+        }
+        if pivot != column {
+            for k in 0..n {
+                matrix.swap(pivot * n + k, column * n + k);
+            }
+            rhs.swap(pivot, column);
+        }
+        let diagonal = matrix[column * n + column];
+        for row in column + 1..n {
+            let factor = matrix[row * n + column] / diagonal;
+            if factor == 0.0 {
+                continue;
+            }
+            for k in column..n {
+                matrix[row * n + k] -= factor * matrix[column * n + k];
+            }
+            rhs[row] -= factor * rhs[column];
+        }
+    }
+    let mut solution = vec![0f64; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n)
+            .map(|k| matrix[row * n + k] * solution[k])
+            .sum();
+        solution[row] = (rhs[row] - sum) / matrix[row * n + row];
+    }
+    Some(solution)
+}
@@ -0,0 +1,113 @@
+use cache::PointCacheReader;
+use data::{DataPoint, TemperaturePoint};
+use grid::Grid;
+use ingest::IngestFilter;
+use math::{Point, RangeBox};
+use rayon::prelude::*;
+use station::StationTable;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+pub mod elevation;
+pub mod idw;
+pub mod kriging;
+pub mod linear;
+pub mod neighbours;
+pub mod rbf;
+pub mod tin;
+pub mod variogram;
+
+pub use self::neighbours::{Distance, NeighbourIndex, EARTH_RADIUS_KM};
+
+// Methods that estimate a value anywhere from scattered samples
+pub trait Interpolator: Sync {
+    // None where there are no samples close enough to estimate from
+    fn estimate(&self, position: Point<f32>) -> Option<f32>;
+}
+
+// Position of the centre of a cell, the same as HeatMap::locate puts points in
+pub fn cell_position(
+    index: [usize; 2],
+    dimensions: (usize, usize),
+    range: &RangeBox<f32>,
+) -> Point<f32> {
+    Point::new(
+        range.horizontal.from + index[0] as f32 * range.horizontal.length() / dimensions.0 as f32,
+        range.vertical.from + index[1] as f32 * range.vertical.length() / dimensions.1 as f32,
+    )
+}
+
+// Estimates every cell of a grid of the given size covering the range
+pub fn interpolate_grid<I: Interpolator>(
+    interpolator: &I,
+    dimensions: (usize, usize),
+    range: &RangeBox<f32>,
+) -> Grid<Option<f32>> {
+    let values = (0..dimensions.0 * dimensions.1)
+        .into_par_iter()
+        .map(|index| {
+            let position = cell_position(
+                [index % dimensions.0, index / dimensions.0],
+                dimensions,
+                range,
+            );
+            interpolator.estimate(position)
+        })
+        .collect();
+    Grid::new_from_values(dimensions.0, dimensions.1, values)
+}
+
+// Averages points at the same position, so a station reporting every month is one sample.
+// Samples are sorted by position so results don't depend on the order points were read in
+pub fn station_means(points: impl IntoIterator<Item = DataPoint<f32>>) -> Vec<DataPoint<f32>> {
+    let mut sums: HashMap<(u32, u32), (Point<f32>, f64, u32)> = HashMap::new();
+    for point in points {
+        if !point.data.is_finite() {
+            continue;
+        }
+        let key = (point.position.x.to_bits(), point.position.y.to_bits());
+        let sum = sums.entry(key).or_insert((point.position, 0.0, 0));
+        sum.1 += f64::from(point.data);
+        sum.2 += 1;
+    }
+    let mut samples: Vec<DataPoint<f32>> = sums
+        .values()
+        .map(|&(position, sum, count)| DataPoint::new(position, (sum / f64::from(count)) as f32))
+        .collect();
+    samples.sort_by(|first, second| {
+        (first.position.x, first.position.y)
+            .partial_cmp(&(second.position.x, second.position.y))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    samples
+}
+
+// Station means of the points in a point cache that pass the filter. Points outside the map
+// are kept on purpose, stations just past the edge still inform the cells along it. Filtering
+// on stations or elevations needs the StationTable saved next to the point cache
+pub fn load_station_means(
+    path: impl AsRef<Path>,
+    filter: &IngestFilter,
+) -> Result<Vec<DataPoint<f32>>, Box<Error>> {
+    let stations = if filter.uses_stations() {
+        StationTable::load_from_bin(StationTable::path_for(&path))?
+    } else {
+        StationTable::new()
+    };
+    let values: PointCacheReader<TemperaturePoint> = PointCacheReader::open(path)?;
+    let mut points = Vec::new();
+    for point in values {
+        let point = point?;
+        let station = stations.station_of(&point);
+        let check = filter.check_point(
+            &point,
+            station.map(|station| station.id.as_str()),
+            station.and_then(|station| station.elevation),
+        );
+        if check.is_ok() {
+            points.push(point.data);
+        }
+    }
+    Ok(station_means(points))
+}
@@ -0,0 +1,214 @@
+use data::DataPoint;
+use math::Point;
+
+pub const EARTH_RADIUS_KM: f32 = 6371.0;
+
+// How distances between positions are measured. Planar distances are in degrees of longitude
+// and latitude, great circle distances are in kilometres over the earth's surface
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Distance {
+    Planar,
+    GreatCircle,
+}
+
+impl Distance {
+    pub fn between(self, first: Point<f32>, second: Point<f32>) -> f32 {
+        match self {
+            Distance::Planar => {
+                let (dx, dy) = (first.x - second.x, first.y - second.y);
+                (dx * dx + dy * dy).sqrt()
+            }
+            // Haversine formula, accurate for short distances too
+            Distance::GreatCircle => {
+                let (lat1, lat2) = (first.y.to_radians(), second.y.to_radians());
+                let dlat = lat2 - lat1;
+                let dlon = (second.x - first.x).to_radians();
+                let a = (dlat / 2.0).sin().powi(2)
+                    + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+                2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+            }
+        }
+    }
+
+    // Coordinates the index searches in. Great circle positions go on the unit sphere, where
+    // the straight line distance grows with the distance over the surface
+    fn embed(self, position: Point<f32>) -> [f32; 3] {
+        match self {
+            Distance::Planar => [position.x, position.y, 0.0],
+            Distance::GreatCircle => {
+                let (lon, lat) = (position.x.to_radians(), position.y.to_radians());
+                [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+            }
+        }
+    }
+
+    // Distance in embedded coordinates of a distance in this metric
+    fn to_embedded(self, distance: f32) -> f32 {
+        match self {
+            Distance::Planar => distance,
+            Distance::GreatCircle => {
+                let angle = (distance / EARTH_RADIUS_KM).min(std::f32::consts::PI);
+                2.0 * (angle / 2.0).sin()
+            }
+        }
+    }
+}
+
+fn squared_distance(first: &[f32; 3], second: &[f32; 3]) -> f32 {
+    (0..3)
+        .map(|axis| (first[axis] - second[axis]).powi(2))
+        .sum()
+}
+
+// Static k-d tree over sample positions for finding the nearest samples to a position.
+// The samples are reordered so each slice has its median in the middle, split on the axis of
+// its depth. The samples can carry any data
+pub struct NeighbourIndex<T: Copy = f32> {
+    distance: Distance,
+    samples: Vec<DataPoint<T>>,
+    coordinates: Vec<[f32; 3]>,
+}
+
+impl<T: Copy> NeighbourIndex<T> {
+    pub fn new(samples: Vec<DataPoint<T>>, distance: Distance) -> Self {
+        let mut pairs: Vec<([f32; 3], DataPoint<T>)> = samples
+            .into_iter()
+            .map(|sample| (distance.embed(sample.position), sample))
+            .collect();
+        let axes = match distance {
+            Distance::Planar => 2,
+            Distance::GreatCircle => 3,
+        };
+        Self::build(&mut pairs, 0, axes);
+        let (coordinates, samples) = pairs.into_iter().unzip();
+        Self {
+            distance,
+            samples,
+            coordinates,
+        }
+    }
+
+    fn build(pairs: &mut [([f32; 3], DataPoint<T>)], depth: usize, axes: usize) {
+        if pairs.len() <= 1 {
+            return;
+        }
+        let axis = depth % axes;
+        let middle = pairs.len() / 2;
+        pairs.select_nth_unstable_by(middle, |first, second| {
+            first.0[axis]
+                .partial_cmp(&second.0[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let (left, right) = pairs.split_at_mut(middle);
+        Self::build(left, depth + 1, axes);
+        Self::build(&mut right[1..], depth + 1, axes);
+    }
+
+    pub fn distance(&self) -> Distance {
+        self.distance
+    }
+
+    pub fn samples(&self) -> &[DataPoint<T>] {
+        &self.samples
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    // Samples nearest to the position as (index into samples, distance), nearest first. At most
+    // max_count are returned and only those within the radius, either can be unlimited
+    pub fn nearest(
+        &self,
+        position: Point<f32>,
+        max_count: Option<usize>,
+        radius: Option<f32>,
+    ) -> Vec<(usize, f32)> {
+        let target = self.distance.embed(position);
+        let mut search = Search {
+            coordinates: &self.coordinates,
+            target,
+            axes: match self.distance {
+                Distance::Planar => 2,
+                Distance::GreatCircle => 3,
+            },
+            max_count: max_count.unwrap_or(usize::MAX),
+            // Slightly larger so samples right on the radius aren't lost to rounding, they are
+            // checked against the real distance below
+            limit: radius.map_or(f32::INFINITY, |radius| {
+                (self.distance.to_embedded(radius) * 1.0001).powi(2)
+            }),
+            found: Vec::new(),
+        };
+        if search.max_count > 0 {
+            search.visit(0, self.coordinates.len(), 0);
+        }
+        search
+            .found
+            .into_iter()
+            .map(|(index, _)| {
+                (
+                    index,
+                    self.distance
+                        .between(position, self.samples[index].position),
+                )
+            })
+            .filter(|&(_, distance)| radius.is_none_or(|radius| distance <= radius))
+            .collect()
+    }
+}
+
+struct Search<'a> {
+    coordinates: &'a [[f32; 3]],
+    target: [f32; 3],
+    axes: usize,
+    max_count: usize,
+    // Squared embedded distance beyond which samples aren't wanted
+    limit: f32,
+    // Sorted by squared embedded distance
+    found: Vec<(usize, f32)>,
+}
+
+impl<'a> Search<'a> {
+    // Worst distance that could still be added
+    fn bound(&self) -> f32 {
+        if self.found.len() < self.max_count {
+            self.limit
+        } else {
+            self.found[self.found.len() - 1].1.min(self.limit)
+        }
+    }
+
+    fn visit(&mut self, start: usize, end: usize, depth: usize) {
+        if start >= end {
+            return;
+        }
+        let middle = start + (end - start) / 2;
+        let point = self.coordinates[middle];
+        let squared = squared_distance(&point, &self.target);
+        if squared <= self.bound() {
+            let at = self
+                .found
+                .iter()
+                .position(|&(_, other)| other > squared)
+                .unwrap_or(self.found.len());
+            self.found.insert(at, (middle, squared));
+            self.found.truncate(self.max_count);
+        }
+        let axis = depth % self.axes;
+        let difference = self.target[axis] - point[axis];
+        let (near, far) = if difference < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+        self.visit(near.0, near.1, depth + 1);
+        if difference * difference <= self.bound() {
+            self.visit(far.0, far.1, depth + 1);
+        }
+    }
+}
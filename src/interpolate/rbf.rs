@@ -0,0 +1,258 @@
+use data::DataPoint;
+use grid::Grid;
+use interpolate::linear::solve;
+use interpolate::neighbours::{Distance, NeighbourIndex, EARTH_RADIUS_KM};
+use interpolate::{cell_position, Interpolator};
+use math::{Point, RangeBox};
+use rayon::prelude::*;
+
+// Terms of the linear polynomial added to the kernels, 1, x and y
+const POLYNOMIAL_TERMS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Kernel {
+    // r^2 ln r, the smoothest surface through the samples with no shape to tune
+    ThinPlate,
+    // -sqrt(1 + r^2), negated so smoothing works the same way as for the other kernels
+    Multiquadric,
+    // exp(-r^2), goes flat between samples further apart than the scale
+    Gaussian,
+}
+
+impl Kernel {
+    // Value at a distance in units of the scale
+    fn value(self, r: f64) -> f64 {
+        match self {
+            Kernel::ThinPlate if r <= 0.0 => 0.0,
+            Kernel::ThinPlate => r * r * r.ln(),
+            Kernel::Multiquadric => -(1.0 + r * r).sqrt(),
+            Kernel::Gaussian => (-r * r).exp(),
+        }
+    }
+}
+
+// Radial basis functions, each estimate is a sum of kernels centred on the nearest samples
+// plus a plane, with weights that make the surface pass through those samples. Solving only
+// over the nearest samples keeps it fast with tens of thousands of stations
+pub struct Rbf {
+    index: NeighbourIndex,
+    pub kernel: Kernel,
+    // Added to the diagonal of the system, 0 passes through every sample and larger values
+    // give a smoother surface that no longer does
+    pub smoothing: f32,
+    // Distance the kernels are scaled by, in the units of the distance. None uses the mean
+    // distance from each sample of a neighbourhood to the closest other one
+    pub scale: Option<f32>,
+    pub max_neighbours: usize,
+    // In the units of the distance, samples further away aren't used
+    pub radius: Option<f32>,
+    // Cells with fewer samples in reach are left as None, at least 3 are needed for the plane
+    pub min_neighbours: usize,
+}
+
+// Weights solved over one neighbourhood, which can estimate any position near it
+struct LocalFit {
+    neighbours: Vec<usize>,
+    weights: Vec<f64>,
+    polynomial: [f64; POLYNOMIAL_TERMS],
+    origin: Point<f32>,
+    scale: f64,
+}
+
+impl Rbf {
+    pub fn new(samples: Vec<DataPoint<f32>>, distance: Distance, kernel: Kernel) -> Self {
+        Self {
+            index: NeighbourIndex::new(samples, distance),
+            kernel,
+            smoothing: 0.0,
+            scale: None,
+            max_neighbours: 24,
+            radius: None,
+            min_neighbours: 3,
+        }
+    }
+
+    pub fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.max(0.0);
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    pub fn with_max_neighbours(mut self, max_neighbours: usize) -> Self {
+        self.max_neighbours = max_neighbours.max(POLYNOMIAL_TERMS);
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+
+    pub fn with_min_neighbours(mut self, min_neighbours: usize) -> Self {
+        self.min_neighbours = min_neighbours.max(POLYNOMIAL_TERMS);
+        self
+    }
+
+    pub fn samples(&self) -> &[DataPoint<f32>] {
+        self.index.samples()
+    }
+
+    // Offset of the position from the origin east and north, in the units of the distance.
+    // Great circle offsets are projected onto the plane touching the earth at the origin,
+    // which stays well behaved across the antimeridian and at the poles
+    fn offset(&self, origin: Point<f32>, position: Point<f32>) -> [f64; 2] {
+        match self.index.distance() {
+            Distance::Planar => [
+                f64::from(position.x - origin.x),
+                f64::from(position.y - origin.y),
+            ],
+            Distance::GreatCircle => {
+                let (lon, lat) = (
+                    f64::from(origin.x).to_radians(),
+                    f64::from(origin.y).to_radians(),
+                );
+                let (plon, plat) = (
+                    f64::from(position.x).to_radians(),
+                    f64::from(position.y).to_radians(),
+                );
+                let point = [plat.cos() * plon.cos(), plat.cos() * plon.sin(), plat.sin()];
+                let east = [-lon.sin(), lon.cos(), 0.0];
+                let north = [-lat.sin() * lon.cos(), -lat.sin() * lon.sin(), lat.cos()];
+                let dot = |axis: [f64; 3]| (0..3).map(|i| axis[i] * point[i]).sum::<f64>();
+                let radius = f64::from(EARTH_RADIUS_KM);
+                [dot(east) * radius, dot(north) * radius]
+            }
+        }
+    }
+
+    // Solves for the weights over the samples nearest the position
+    fn fit(&self, position: Point<f32>, neighbours: Vec<(usize, f32)>) -> Option<LocalFit> {
+        if neighbours.len() < self.min_neighbours.max(POLYNOMIAL_TERMS) {
+            return None;
+        }
+        let samples = self.index.samples();
+        let distance = self.index.distance();
+        let n = neighbours.len();
+        let mut lags = vec![0f64; n * n];
+        for (row, &(first, _)) in neighbours.iter().enumerate() {
+            for (column, &(second, _)) in neighbours.iter().enumerate().skip(row + 1) {
+                let lag = distance.between(samples[first].position, samples[second].position);
+                lags[row * n + column] = f64::from(lag);
+                lags[column * n + row] = f64::from(lag);
+            }
+        }
+        // Depends only on the samples, so every position with the same neighbourhood gets
+        // the same weights
+        let scale = match self.scale {
+            Some(scale) => f64::from(scale),
+            None => {
+                (0..n)
+                    .map(|row| {
+                        (0..n)
+                            .filter(|&column| column != row)
+                            .map(|column| lags[row * n + column])
+                            .fold(f64::INFINITY, f64::min)
+                    })
+                    .sum::<f64>()
+                    / n as f64
+            }
+        };
+        if scale <= 0.0 || !scale.is_finite() {
+            return None;
+        }
+        let size = n + POLYNOMIAL_TERMS;
+        // Kernels between the samples bordered by the plane's terms at each sample, with the
+        // weights kept from having any plane of their own
+        let mut matrix = vec![0f64; size * size];
+        for (row, &(first, _)) in neighbours.iter().enumerate() {
+            for column in 0..n {
+                matrix[row * size + column] = self.kernel.value(lags[row * n + column] / scale);
+            }
+            matrix[row * size + row] += f64::from(self.smoothing);
+            let [x, y] = self.offset(position, samples[first].position);
+            for (term, value) in [1.0, x / scale, y / scale].iter().enumerate() {
+                matrix[row * size + n + term] = *value;
+                matrix[(n + term) * size + row] = *value;
+            }
+        }
+        let mut rhs: Vec<f64> = neighbours
+            .iter()
+            .map(|&(index, _)| f64::from(samples[index].data))
+            .collect();
+        rhs.extend_from_slice(&[0.0; POLYNOMIAL_TERMS]);
+        let mut weights = solve(matrix, rhs)?;
+        let polynomial = [weights[n], weights[n + 1], weights[n + 2]];
+        weights.truncate(n);
+        Some(LocalFit {
+            neighbours: neighbours.into_iter().map(|(index, _)| index).collect(),
+            weights,
+            polynomial,
+            origin: position,
+            scale,
+        })
+    }
+
+    fn evaluate(&self, fit: &LocalFit, position: Point<f32>) -> f32 {
+        let samples = self.index.samples();
+        let distance = self.index.distance();
+        let kernels: f64 = fit
+            .neighbours
+            .iter()
+            .zip(&fit.weights)
+            .map(|(&index, weight)| {
+                let lag = distance.between(position, samples[index].position);
+                weight * self.kernel.value(f64::from(lag) / fit.scale)
+            })
+            .sum();
+        let [x, y] = self.offset(fit.origin, position);
+        let plane = fit.polynomial[0]
+            + fit.polynomial[1] * x / fit.scale
+            + fit.polynomial[2] * y / fit.scale;
+        (kernels + plane) as f32
+    }
+
+    fn neighbours(&self, position: Point<f32>) -> Vec<(usize, f32)> {
+        self.index
+            .nearest(position, Some(self.max_neighbours), self.radius)
+    }
+
+    // Grid of the given size covering the range. Rows are done in parallel, and cells next to
+    // each other with the same nearest samples share their weights instead of solving again
+    pub fn grid(&self, dimensions: (usize, usize), range: &RangeBox<f32>) -> Grid<Option<f32>> {
+        let rows: Vec<Vec<Option<f32>>> = (0..dimensions.1)
+            .into_par_iter()
+            .map(|y| {
+                let mut previous: Option<(Vec<usize>, Option<LocalFit>)> = None;
+                (0..dimensions.0)
+                    .map(|x| {
+                        let position = cell_position([x, y], dimensions, range);
+                        let neighbours = self.neighbours(position);
+                        let mut key: Vec<usize> =
+                            neighbours.iter().map(|&(index, _)| index).collect();
+                        key.sort_unstable();
+                        let reuse = previous.as_ref().is_some_and(|(last, _)| *last == key);
+                        if !reuse {
+                            previous = Some((key, self.fit(position, neighbours)));
+                        }
+                        previous
+                            .as_ref()
+                            .and_then(|(_, fit)| fit.as_ref())
+                            .map(|fit| self.evaluate(fit, position))
+                    })
+                    .collect()
+            })
+            .collect();
+        Grid::new_from_values(dimensions.0, dimensions.1, rows.concat())
+    }
+}
+
+impl Interpolator for Rbf {
+    fn estimate(&self, position: Point<f32>) -> Option<f32> {
+        let fit = self.fit(position, self.neighbours(position))?;
+        Some(self.evaluate(&fit, position))
+    }
+}
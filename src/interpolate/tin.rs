@@ -0,0 +1,494 @@
+use data::DataPoint;
+use grid::Grid;
+use interpolate::neighbours::{Distance, NeighbourIndex};
+use interpolate::{cell_position, Interpolator};
+use math::{Point, RangeBox};
+use rayon::prelude::*;
+use serde_json;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// How much larger than the samples' extent the enclosing triangle is. Larger keeps more of the
+// triangles along the hull but costs precision
+const ENCLOSING_SCALE: f64 = 100.0;
+
+// What estimates outside the convex hull of the samples are
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum HullPolicy {
+    // Left as None
+    Empty,
+    // The value of the nearest sample
+    Nearest,
+    // The value at the closest point on the hull, interpolated along the hull edge
+    Edge,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Triangle {
+    // Counter clockwise
+    vertices: [usize; 3],
+    // Triangle across the edge opposite each vertex
+    neighbours: [Option<usize>; 3],
+    // Removed while inserting a sample, dropped once the triangulation is built
+    dead: bool,
+}
+
+// Twice the signed area of abc, positive when counter clockwise
+fn orient(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+// Positive when d is inside the circumcircle of the counter clockwise triangle abc
+fn in_circle(a: [f64; 2], b: [f64; 2], c: [f64; 2], d: [f64; 2]) -> f64 {
+    let (ax, ay) = (a[0] - d[0], a[1] - d[1]);
+    let (bx, by) = (b[0] - d[0], b[1] - d[1]);
+    let (cx, cy) = (c[0] - d[0], c[1] - d[1]);
+    (ax * ax + ay * ay) * (bx * cy - cx * by) - (bx * bx + by * by) * (ax * cy - cx * ay)
+        + (cx * cx + cy * cy) * (ax * by - bx * ay)
+}
+
+// Triangulated irregular network, a Delaunay triangulation of the samples in longitude and
+// latitude that estimates linearly inside each triangle. The triangulation doesn't wrap
+// around the antimeridian
+pub struct Tin {
+    index: NeighbourIndex,
+    // Sample positions relative to their centre, followed by the corners of a triangle
+    // enclosing them all
+    points: Vec<[f64; 2]>,
+    centre: [f64; 2],
+    // Including the triangles that touch the enclosing corners, they make finding the triangle
+    // of any position a walk from one triangle to the next
+    triangles: Vec<Triangle>,
+    // Edges of the triangulation on its hull
+    hull: Vec<[usize; 2]>,
+    pub policy: HullPolicy,
+    // In degrees, positions further outside the hull are left as None whatever the policy
+    pub max_extrapolation: Option<f32>,
+}
+
+impl Tin {
+    // Samples at the same position as an earlier one are left out of the triangulation
+    pub fn new(samples: Vec<DataPoint<f32>>) -> Self {
+        // The index orders samples so neighbours in the order are near each other, which keeps
+        // the walks while inserting them short
+        let index = NeighbourIndex::new(samples, Distance::Planar);
+        let samples = index.samples();
+        let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+        for sample in samples {
+            let position = [f64::from(sample.position.x), f64::from(sample.position.y)];
+            for axis in 0..2 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        let (centre, extent) = if samples.is_empty() {
+            ([0.0, 0.0], 1.0)
+        } else {
+            (
+                [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0],
+                (max[0] - min[0]).max(max[1] - min[1]).max(1.0),
+            )
+        };
+        let mut points: Vec<[f64; 2]> = samples
+            .iter()
+            .map(|sample| {
+                [
+                    f64::from(sample.position.x) - centre[0],
+                    f64::from(sample.position.y) - centre[1],
+                ]
+            })
+            .collect();
+        let size = extent * ENCLOSING_SCALE;
+        points.push([-size, -size]);
+        points.push([size, -size]);
+        points.push([0.0, size]);
+        let count = samples.len();
+        let mut tin = Self {
+            index,
+            points,
+            centre,
+            triangles: vec![Triangle {
+                vertices: [count, count + 1, count + 2],
+                neighbours: [None; 3],
+                dead: false,
+            }],
+            hull: Vec::new(),
+            policy: HullPolicy::Empty,
+            max_extrapolation: None,
+        };
+        let mut hint = 0;
+        for vertex in 0..count {
+            hint = tin.insert(vertex, hint);
+        }
+        tin.compact();
+        tin.find_hull();
+        tin
+    }
+
+    pub fn with_policy(mut self, policy: HullPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_max_extrapolation(mut self, max_extrapolation: f32) -> Self {
+        self.max_extrapolation = Some(max_extrapolation);
+        self
+    }
+
+    pub fn samples(&self) -> &[DataPoint<f32>] {
+        self.index.samples()
+    }
+
+    // Triangles between samples as indices into samples, counter clockwise
+    pub fn triangles<'a>(&'a self) -> impl Iterator<Item = [usize; 3]> + 'a {
+        self.triangles
+            .iter()
+            .filter(move |triangle| self.is_inside(triangle))
+            .map(|triangle| triangle.vertices)
+    }
+
+    // Whether the triangle is between samples rather than touching the enclosing corners
+    fn is_inside(&self, triangle: &Triangle) -> bool {
+        let count = self.index.len();
+        triangle.vertices.iter().all(|&vertex| vertex < count)
+    }
+
+    // Bowyer-Watson insertion. Every triangle whose circumcircle holds the new vertex is
+    // removed and the hole is filled with triangles fanning out from the vertex. Returns a
+    // new triangle to start the next search from
+    fn insert(&mut self, vertex: usize, hint: usize) -> usize {
+        let point = self.points[vertex];
+        let first = self.locate(point, hint);
+        if self.triangles[first]
+            .vertices
+            .iter()
+            .any(|&other| self.points[other] == point)
+        {
+            return first;
+        }
+        self.triangles[first].dead = true;
+        let mut cavity = vec![first];
+        let mut stack = vec![first];
+        while let Some(current) = stack.pop() {
+            let neighbours = self.triangles[current].neighbours;
+            for &neighbour in neighbours.iter().flatten() {
+                if !self.triangles[neighbour].dead && self.circumcircle_holds(neighbour, point) {
+                    self.triangles[neighbour].dead = true;
+                    cavity.push(neighbour);
+                    stack.push(neighbour);
+                }
+            }
+        }
+        // Edges around the hole as (from, to, triangle outside, triangle inside). Rounding can
+        // leave the vertex on or behind one of them, the triangle beyond it then joins the hole
+        let boundary = loop {
+            let mut boundary = Vec::new();
+            let mut behind = Vec::new();
+            for &inside in &cavity {
+                let triangle = self.triangles[inside];
+                for edge in 0..3 {
+                    let outside = triangle.neighbours[edge];
+                    if outside.is_some_and(|outside| self.triangles[outside].dead) {
+                        continue;
+                    }
+                    let from = triangle.vertices[(edge + 1) % 3];
+                    let to = triangle.vertices[(edge + 2) % 3];
+                    match outside {
+                        Some(outside)
+                            if orient(self.points[from], self.points[to], point) <= 0.0 =>
+                        {
+                            behind.push(outside)
+                        }
+                        _ => boundary.push((from, to, outside, inside)),
+                    }
+                }
+            }
+            if behind.is_empty() {
+                break boundary;
+            }
+            for outside in behind {
+                if !self.triangles[outside].dead {
+                    self.triangles[outside].dead = true;
+                    cavity.push(outside);
+                }
+            }
+        };
+        let start = self.triangles.len();
+        for (offset, &(from, to, outside, inside)) in boundary.iter().enumerate() {
+            if let Some(outside) = outside {
+                for neighbour in self.triangles[outside].neighbours.iter_mut() {
+                    if *neighbour == Some(inside) {
+                        *neighbour = Some(start + offset);
+                    }
+                }
+            }
+            self.triangles.push(Triangle {
+                vertices: [from, to, vertex],
+                neighbours: [None, None, outside],
+                dead: false,
+            });
+        }
+        // The new triangles share their edges to the vertex with the ones either side, found
+        // by where their outer edges start and end
+        for (offset, &(from, to, _, _)) in boundary.iter().enumerate() {
+            let next = boundary.iter().position(|edge| edge.0 == to);
+            let previous = boundary.iter().position(|edge| edge.1 == from);
+            let triangle = &mut self.triangles[start + offset];
+            triangle.neighbours[0] = next.map(|next| start + next);
+            triangle.neighbours[1] = previous.map(|previous| start + previous);
+        }
+        self.triangles.len() - 1
+    }
+
+    fn circumcircle_holds(&self, triangle: usize, point: [f64; 2]) -> bool {
+        let [a, b, c] = self.triangles[triangle].vertices;
+        in_circle(self.points[a], self.points[b], self.points[c], point) > 0.0
+    }
+
+    // Walks from the hint towards the point, crossing any edge the point is behind. Ends on the
+    // triangle holding the point, or on the edge of the enclosing triangle if it's outside that
+    fn locate(&self, point: [f64; 2], hint: usize) -> usize {
+        let mut current = hint;
+        for _ in 0..self.triangles.len() {
+            let triangle = &self.triangles[current];
+            let next = (0..3).find_map(|edge| {
+                let from = self.points[triangle.vertices[(edge + 1) % 3]];
+                let to = self.points[triangle.vertices[(edge + 2) % 3]];
+                if orient(from, to, point) < 0.0 {
+                    triangle.neighbours[edge]
+                } else {
+                    None
+                }
+            });
+            match next {
+                Some(next) => current = next,
+                None => return current,
+            }
+        }
+        // Rounding can send the walk in circles, every triangle is checked instead
+        self.triangles
+            .iter()
+            .position(|triangle| {
+                !triangle.dead
+                    && (0..3).all(|edge| {
+                        let from = self.points[triangle.vertices[(edge + 1) % 3]];
+                        let to = self.points[triangle.vertices[(edge + 2) % 3]];
+                        orient(from, to, point) >= 0.0
+                    })
+            })
+            .unwrap_or(current)
+    }
+
+    // Drops the removed triangles
+    fn compact(&mut self) {
+        let mut moved = vec![None; self.triangles.len()];
+        let mut kept = 0;
+        for (old, triangle) in self.triangles.iter().enumerate() {
+            if !triangle.dead {
+                moved[old] = Some(kept);
+                kept += 1;
+            }
+        }
+        self.triangles.retain(|triangle| !triangle.dead);
+        for triangle in &mut self.triangles {
+            for neighbour in triangle.neighbours.iter_mut() {
+                *neighbour = neighbour.and_then(|old| moved[old]);
+            }
+        }
+    }
+
+    fn find_hull(&mut self) {
+        let mut hull = Vec::new();
+        for triangle in self.triangles.iter().filter(|t| self.is_inside(t)) {
+            for edge in 0..3 {
+                let outside = triangle.neighbours[edge]
+                    .is_none_or(|neighbour| !self.is_inside(&self.triangles[neighbour]));
+                if outside {
+                    hull.push([
+                        triangle.vertices[(edge + 1) % 3],
+                        triangle.vertices[(edge + 2) % 3],
+                    ]);
+                }
+            }
+        }
+        self.hull = hull;
+    }
+
+    fn local(&self, position: Point<f32>) -> [f64; 2] {
+        [
+            f64::from(position.x) - self.centre[0],
+            f64::from(position.y) - self.centre[1],
+        ]
+    }
+
+    // Estimate at the position, found by walking from the hint. Returns the triangle the walk
+    // ended on to start the next walk from
+    fn estimate_from(&self, position: Point<f32>, hint: usize) -> (Option<f32>, usize) {
+        let point = self.local(position);
+        let found = self.locate(point, hint);
+        let triangle = &self.triangles[found];
+        if !self.is_inside(triangle) {
+            return (self.extrapolate(position, point), found);
+        }
+        let [a, b, c] = triangle.vertices;
+        let (pa, pb, pc) = (self.points[a], self.points[b], self.points[c]);
+        let area = orient(pa, pb, pc);
+        let samples = self.index.samples();
+        // Barycentric weights, each the share of the area opposite its vertex
+        let value = (orient(pb, pc, point) * f64::from(samples[a].data)
+            + orient(pc, pa, point) * f64::from(samples[b].data)
+            + orient(pa, pb, point) * f64::from(samples[c].data))
+            / area;
+        (Some(value as f32), found)
+    }
+
+    // Closest point on the hull as (distance, hull edge, position along it). None when the
+    // samples are collinear or fewer than 3, which leaves the hull empty
+    fn closest_on_hull(&self, point: [f64; 2]) -> Option<(f64, [usize; 2], f64)> {
+        self.hull
+            .iter()
+            .map(|&[from, to]| {
+                let (start, end) = (self.points[from], self.points[to]);
+                let direction = [end[0] - start[0], end[1] - start[1]];
+                let length = direction[0] * direction[0] + direction[1] * direction[1];
+                let along = if length > 0.0 {
+                    (((point[0] - start[0]) * direction[0] + (point[1] - start[1]) * direction[1])
+                        / length)
+                        .clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let closest = [
+                    start[0] + along * direction[0],
+                    start[1] + along * direction[1],
+                ];
+                let distance = (point[0] - closest[0]).hypot(point[1] - closest[1]);
+                (distance, [from, to], along)
+            })
+            .min_by(|first, second| {
+                first
+                    .0
+                    .partial_cmp(&second.0)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    fn within_extrapolation(&self, distance: f64) -> bool {
+        self.max_extrapolation
+            .is_none_or(|max| distance <= f64::from(max))
+    }
+
+    fn extrapolate(&self, position: Point<f32>, point: [f64; 2]) -> Option<f32> {
+        let samples = self.index.samples();
+        match self.policy {
+            HullPolicy::Empty => None,
+            HullPolicy::Nearest => {
+                let &(nearest, lag) = self.index.nearest(position, Some(1), None).first()?;
+                if self.max_extrapolation.is_some() {
+                    // Without a hull the nearest sample is as close as the samples reach
+                    let distance = self
+                        .closest_on_hull(point)
+                        .map_or(f64::from(lag), |(distance, _, _)| distance);
+                    if !self.within_extrapolation(distance) {
+                        return None;
+                    }
+                }
+                Some(samples[nearest].data)
+            }
+            HullPolicy::Edge => {
+                let (distance, edge, along) = self.closest_on_hull(point)?;
+                if !self.within_extrapolation(distance) {
+                    return None;
+                }
+                let (from, to) = (
+                    f64::from(samples[edge[0]].data),
+                    f64::from(samples[edge[1]].data),
+                );
+                Some((from + along * (to - from)) as f32)
+            }
+        }
+    }
+
+    // Grid of the given size covering the range. Rows are done in parallel, each walking from
+    // one cell's triangle to the next
+    pub fn grid(&self, dimensions: (usize, usize), range: &RangeBox<f32>) -> Grid<Option<f32>> {
+        let rows: Vec<Vec<Option<f32>>> = (0..dimensions.1)
+            .into_par_iter()
+            .map(|y| {
+                let mut hint = 0;
+                (0..dimensions.0)
+                    .map(|x| {
+                        let position = cell_position([x, y], dimensions, range);
+                        let (value, found) = self.estimate_from(position, hint);
+                        hint = found;
+                        value
+                    })
+                    .collect()
+            })
+            .collect();
+        Grid::new_from_values(dimensions.0, dimensions.1, rows.concat())
+    }
+
+    // Triangles as GeoJSON polygons with the values at their corners and their longest edge in
+    // degrees, followed by the samples as points. Long edges show where stations are sparse
+    pub fn to_geojson(&self) -> Value {
+        let samples = self.index.samples();
+        let coordinates =
+            |vertex: usize| json!([samples[vertex].position.x, samples[vertex].position.y]);
+        let mut features: Vec<Value> = self
+            .triangles()
+            .map(|vertices| {
+                let longest_edge = (0..3)
+                    .map(|corner| {
+                        Distance::Planar.between(
+                            samples[vertices[corner]].position,
+                            samples[vertices[(corner + 1) % 3]].position,
+                        )
+                    })
+                    .fold(0f32, f32::max);
+                json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[
+                            coordinates(vertices[0]),
+                            coordinates(vertices[1]),
+                            coordinates(vertices[2]),
+                            coordinates(vertices[0]),
+                        ]],
+                    },
+                    "properties": {
+                        "values": [
+                            samples[vertices[0]].data,
+                            samples[vertices[1]].data,
+                            samples[vertices[2]].data,
+                        ],
+                        "longest_edge": longest_edge,
+                    },
+                })
+            })
+            .collect();
+        features.extend((0..samples.len()).map(|vertex| {
+            json!({
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": coordinates(vertex) },
+                "properties": { "value": samples[vertex].data },
+            })
+        }));
+        json!({ "type": "FeatureCollection", "features": features })
+    }
+
+    pub fn save_geojson(&self, path: impl AsRef<Path>) -> Result<(), Box<Error>> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &self.to_geojson())?;
+        Ok(())
+    }
+}
+
+impl Interpolator for Tin {
+    fn estimate(&self, position: Point<f32>) -> Option<f32> {
+        self.estimate_from(position, 0).0
+    }
+}
@@ -0,0 +1,233 @@
+use data::DataPoint;
+use interpolate::neighbours::Distance;
+use math::Point;
+use rayon::prelude::*;
+
+// Ranges tried when fitting a model, spread evenly up to the largest lag
+const FIT_STEPS: usize = 100;
+
+// Semivariance of sample pairs grouped by the distance between them
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct VariogramBin {
+    // Mean distance of the pairs in the bin
+    pub lag: f32,
+    pub semivariance: f32,
+    pub pairs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EmpiricalVariogram {
+    pub distance: Distance,
+    pub max_lag: f32,
+    // Only bins with pairs in them, closest first
+    pub bins: Vec<VariogramBin>,
+}
+
+impl EmpiricalVariogram {
+    // Half the mean squared difference of the pairs in each of bin_count equal bins up to
+    // max_lag. Every pair of samples is compared, so very large sets are slow
+    pub fn new(
+        samples: &[DataPoint<f32>],
+        distance: Distance,
+        max_lag: f32,
+        bin_count: usize,
+    ) -> Self {
+        let bin_count = bin_count.max(1);
+        let width = max_lag / bin_count as f32;
+        let empty = || vec![(0f64, 0f64, 0u64); bin_count];
+        let sums = (0..samples.len())
+            .into_par_iter()
+            .fold(empty, |mut sums, i| {
+                for j in i + 1..samples.len() {
+                    let lag = distance.between(samples[i].position, samples[j].position);
+                    if lag >= max_lag || lag.is_nan() {
+                        continue;
+                    }
+                    let bin = ((lag / width) as usize).min(bin_count - 1);
+                    let difference = f64::from(samples[i].data - samples[j].data);
+                    sums[bin].0 += f64::from(lag);
+                    sums[bin].1 += difference * difference;
+                    sums[bin].2 += 1;
+                }
+                sums
+            })
+            .reduce(empty, |mut first, second| {
+                for (sum, other) in first.iter_mut().zip(second) {
+                    sum.0 += other.0;
+                    sum.1 += other.1;
+                    sum.2 += other.2;
+                }
+                first
+            });
+        let bins = sums
+            .into_iter()
+            .filter(|&(_, _, pairs)| pairs > 0)
+            .map(|(lags, squares, pairs)| VariogramBin {
+                lag: (lags / pairs as f64) as f32,
+                semivariance: (squares / (2.0 * pairs as f64)) as f32,
+                pairs,
+            })
+            .collect();
+        Self {
+            distance,
+            max_lag,
+            bins,
+        }
+    }
+
+    // Bins up to half the diagonal of the samples' bounding box, beyond which there are too
+    // few pairs to rely on
+    pub fn from_samples(samples: &[DataPoint<f32>], distance: Distance, bin_count: usize) -> Self {
+        let (mut min, mut max) = (
+            Point::new(f32::INFINITY, f32::INFINITY),
+            Point::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+        );
+        for sample in samples {
+            min.x = min.x.min(sample.position.x);
+            min.y = min.y.min(sample.position.y);
+            max.x = max.x.max(sample.position.x);
+            max.y = max.y.max(sample.position.y);
+        }
+        let diagonal = if samples.is_empty() {
+            0.0
+        } else {
+            distance.between(min, max)
+        };
+        Self::new(samples, distance, diagonal / 2.0, bin_count)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ModelKind {
+    Spherical,
+    Exponential,
+    Gaussian,
+}
+
+impl ModelKind {
+    pub fn all() -> [ModelKind; 3] {
+        [
+            ModelKind::Spherical,
+            ModelKind::Exponential,
+            ModelKind::Gaussian,
+        ]
+    }
+
+    // Shape of the model rising from 0 to 1, the range is where it reaches 1 or, for the
+    // exponential and Gaussian models which never do, about 95%
+    fn shape(self, lag: f32, range: f32) -> f32 {
+        let ratio = lag / range;
+        match self {
+            ModelKind::Spherical if ratio >= 1.0 => 1.0,
+            ModelKind::Spherical => 1.5 * ratio - 0.5 * ratio.powi(3),
+            ModelKind::Exponential => 1.0 - (-3.0 * ratio).exp(),
+            ModelKind::Gaussian => 1.0 - (-3.0 * ratio * ratio).exp(),
+        }
+    }
+}
+
+// Semivariance as a function of distance, nugget + partial sill * shape(lag / range)
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct VariogramModel {
+    pub kind: ModelKind,
+    pub nugget: f32,
+    pub partial_sill: f32,
+    pub range: f32,
+}
+
+impl VariogramModel {
+    pub fn new(kind: ModelKind, nugget: f32, partial_sill: f32, range: f32) -> Self {
+        Self {
+            kind,
+            nugget,
+            partial_sill,
+            range,
+        }
+    }
+
+    pub fn sill(&self) -> f32 {
+        self.nugget + self.partial_sill
+    }
+
+    pub fn value(&self, lag: f32) -> f32 {
+        if lag <= 0.0 {
+            return 0.0;
+        }
+        self.nugget + self.partial_sill * self.kind.shape(lag, self.range)
+    }
+
+    // Weighted least squares fit, weighting bins by their number of pairs. Each range tried
+    // leaves the nugget and partial sill linear, so those are solved for exactly and kept
+    // from going negative. Returns the model with its squared error, None without bins
+    pub fn fit(kind: ModelKind, variogram: &EmpiricalVariogram) -> Option<(Self, f64)> {
+        let bins = &variogram.bins;
+        let longest = bins.iter().map(|bin| bin.lag).fold(0f32, f32::max);
+        if bins.is_empty() || longest <= 0.0 {
+            return None;
+        }
+        (1..=FIT_STEPS)
+            .map(|step| longest * step as f32 / FIT_STEPS as f32)
+            .map(|range| Self::fit_range(kind, bins, range))
+            .min_by(|first, second| {
+                first
+                    .1
+                    .partial_cmp(&second.1)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    fn fit_range(kind: ModelKind, bins: &[VariogramBin], range: f32) -> (Self, f64) {
+        // Normal equations of semivariance = nugget + partial_sill * shape
+        let (mut w, mut ws, mut wss, mut wy, mut wsy) = (0f64, 0f64, 0f64, 0f64, 0f64);
+        for bin in bins {
+            let weight = bin.pairs as f64;
+            let shape = f64::from(kind.shape(bin.lag, range));
+            let value = f64::from(bin.semivariance);
+            w += weight;
+            ws += weight * shape;
+            wss += weight * shape * shape;
+            wy += weight * value;
+            wsy += weight * shape * value;
+        }
+        let determinant = w * wss - ws * ws;
+        let (mut nugget, mut sill) = if determinant.abs() > 1e-12 {
+            (
+                (wss * wy - ws * wsy) / determinant,
+                (w * wsy - ws * wy) / determinant,
+            )
+        } else {
+            (0.0, if wss > 0.0 { wsy / wss } else { 0.0 })
+        };
+        if nugget < 0.0 {
+            nugget = 0.0;
+            sill = if wss > 0.0 { wsy / wss } else { 0.0 };
+        }
+        if sill < 0.0 {
+            sill = 0.0;
+            nugget = if w > 0.0 { wy / w } else { 0.0 };
+        }
+        let model = Self::new(kind, nugget as f32, sill as f32, range);
+        let error = bins
+            .iter()
+            .map(|bin| {
+                let difference = f64::from(model.value(bin.lag) - bin.semivariance);
+                bin.pairs as f64 * difference * difference
+            })
+            .sum();
+        (model, error)
+    }
+
+    // The kind of model that fits best
+    pub fn fit_best(variogram: &EmpiricalVariogram) -> Option<Self> {
+        ModelKind::all()
+            .iter()
+            .filter_map(|&kind| Self::fit(kind, variogram))
+            .min_by(|first, second| {
+                first
+                    .1
+                    .partial_cmp(&second.1)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(model, _)| model)
+    }
+}
@@ -0,0 +1,295 @@
+use colormap::{Colormap, Rgb};
+use cpu_render::contrast_factor;
+use draw::{fill_rect, outline_rect, to_rgba};
+use font::{draw_text, text_height, text_width};
+use image::{Rgba, RgbaImage};
+use math::Range;
+use units::Unit;
+
+// Colour bar with value ticks, a title and swatches for the under, over and nodata colours.
+// Sizes are in glyph pixels and multiplied by the scale
+pub struct Legend {
+    pub colormap: Colormap,
+    // Values at the bottom and top of the bar
+    pub value_range: Range<f32>,
+    // Same contrast as the map so the bar matches its colours
+    pub contrast: f32,
+    // Shown after the title, tick labels are values in this unit
+    pub unit: Option<Unit>,
+    pub title: Option<String>,
+    // Roughly how many ticks to draw, they are put on round values
+    pub tick_count: usize,
+    // Height of the colour bar in pixels
+    pub bar_length: u32,
+    pub scale: u32,
+    pub swatches: bool,
+    pub text_colour: Rgba<u8>,
+    pub background: Rgba<u8>,
+}
+
+// Width of the bar, length of the ticks and the gaps between parts in glyph pixels
+const BAR_WIDTH: u32 = 10;
+const TICK_LENGTH: u32 = 3;
+const PADDING: u32 = 4;
+const GAP: u32 = 3;
+const SWATCH_SIZE: u32 = 7;
+
+// Round step of 1, 2 or 5 times a power of ten giving about count steps over the length
+pub fn tick_step(length: f32, count: usize) -> f32 {
+    let raw = length / count.max(1) as f32;
+    let magnitude = 10f32.powf(raw.log10().floor());
+    let fraction = raw / magnitude;
+    let nice = if fraction <= 1.0 {
+        1.0
+    } else if fraction <= 2.0 {
+        2.0
+    } else if fraction <= 5.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * magnitude
+}
+
+// Enough decimals to tell ticks step apart
+fn decimals(step: f32) -> usize {
+    (-(step.log10() + 1e-4).floor()).max(0.0) as usize
+}
+
+fn format_value(value: f32, decimals: usize) -> String {
+    let text = format!("{:.*}", decimals, value);
+    // Rounding small negative values leaves "-0"
+    if text.starts_with('-') && text[1..].chars().all(|c| c == '0' || c == '.') {
+        text[1..].to_string()
+    } else {
+        text
+    }
+}
+
+impl Legend {
+    pub fn new(colormap: Colormap, value_range: Range<f32>) -> Self {
+        Self {
+            colormap,
+            value_range,
+            contrast: 0.0,
+            unit: None,
+            title: None,
+            tick_count: 5,
+            bar_length: 200,
+            scale: 2,
+            swatches: true,
+            text_colour: Rgba {
+                data: [0, 0, 0, 255],
+            },
+            background: Rgba {
+                data: [255, 255, 255, 255],
+            },
+        }
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = Some(String::from(title));
+        self
+    }
+
+    pub fn with_unit(mut self, unit: Option<Unit>) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    pub fn with_contrast(mut self, contrast: f32) -> Self {
+        self.contrast = contrast;
+        self
+    }
+
+    pub fn with_tick_count(mut self, tick_count: usize) -> Self {
+        self.tick_count = tick_count;
+        self
+    }
+
+    pub fn with_bar_length(mut self, bar_length: u32) -> Self {
+        self.bar_length = bar_length;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn with_swatches(mut self, swatches: bool) -> Self {
+        self.swatches = swatches;
+        self
+    }
+
+    pub fn with_colours(mut self, text_colour: Rgba<u8>, background: Rgba<u8>) -> Self {
+        self.text_colour = text_colour;
+        self.background = background;
+        self
+    }
+
+    // Title followed by the unit, eg. "Mean (°C)"
+    pub fn heading(&self) -> Option<String> {
+        match (&self.title, self.unit) {
+            (Some(title), Some(unit)) => Some(format!("{} ({})", title, unit.symbol())),
+            (Some(title), None) => Some(title.clone()),
+            (None, Some(unit)) => Some(String::from(unit.symbol())),
+            (None, None) => None,
+        }
+    }
+
+    // Values of the ticks and their labels, from the bottom of the bar to the top
+    pub fn ticks(&self) -> Vec<(f32, String)> {
+        let (min, max) = (self.value_range.from, self.value_range.to);
+        if max <= min || (max - min).is_nan() {
+            return vec![(min, format_value(min, 2))];
+        }
+        let step = tick_step(max - min, self.tick_count);
+        let decimals = decimals(step);
+        let first = (min / step).ceil() as i64;
+        let last = (max / step + 1e-4).floor() as i64;
+        (first..=last)
+            .map(|i| i as f32 * step)
+            .map(|value| (value, format_value(value, decimals)))
+            .collect()
+    }
+
+    fn swatch_labels(&self) -> Vec<(Rgb, String)> {
+        if !self.swatches {
+            return Vec::new();
+        }
+        // One more decimal than the ticks as the ends are rarely round values
+        let step = tick_step(self.value_range.length().abs(), self.tick_count);
+        let decimals = decimals(step / 10.0);
+        vec![
+            (
+                self.colormap.over_colour(),
+                format!("> {}", format_value(self.value_range.to, decimals)),
+            ),
+            (
+                self.colormap.under_colour(),
+                format!("< {}", format_value(self.value_range.from, decimals)),
+            ),
+            (self.colormap.nodata, String::from("No data")),
+        ]
+    }
+
+    // Offset of the colour bar from the top of the legend
+    fn bar_top(&self) -> u32 {
+        let heading = match self.heading() {
+            Some(_) => text_height(self.scale) + GAP * self.scale,
+            None => 0,
+        };
+        // Leaves room for half of the top label
+        PADDING * self.scale + heading + text_height(self.scale) / 2
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        let scale = self.scale;
+        let labels = self
+            .ticks()
+            .iter()
+            .map(|(_, label)| text_width(label, scale))
+            .max()
+            .unwrap_or(0);
+        let bar = (BAR_WIDTH + TICK_LENGTH + GAP) * scale + labels;
+        let heading = self
+            .heading()
+            .map_or(0, |heading| text_width(&heading, scale));
+        let swatch_labels = self.swatch_labels();
+        let swatches = swatch_labels
+            .iter()
+            .map(|(_, label)| (SWATCH_SIZE + GAP) * scale + text_width(label, scale))
+            .max()
+            .unwrap_or(0);
+        let width = bar.max(heading).max(swatches) + 2 * PADDING * scale;
+        let swatch_height = swatch_labels.len() as u32 * (SWATCH_SIZE + GAP) * scale;
+        let height = self.bar_top()
+            + self.bar_length
+            + text_height(scale) / 2
+            + GAP * scale
+            + swatch_height
+            + PADDING * scale;
+        (width, height)
+    }
+
+    pub fn render(&self) -> RgbaImage {
+        let (width, height) = self.dimensions();
+        let mut image = RgbaImage::from_pixel(width, height, self.background);
+        let scale = self.scale;
+        let left = PADDING * scale;
+        if let Some(heading) = self.heading() {
+            draw_text(
+                &mut image,
+                &heading,
+                i64::from(left),
+                i64::from(left),
+                scale,
+                self.text_colour,
+            );
+        }
+
+        // Colours the same as the map, see MapRenderer::render
+        let top = self.bar_top();
+        let bar_width = BAR_WIDTH * scale;
+        let contrast = contrast_factor(self.contrast);
+        for row in 0..self.bar_length {
+            let t = 1.0 - (row as f32 + 0.5) / self.bar_length as f32;
+            let colour = to_rgba(self.colormap.sample(contrast * (t - 0.5) + 0.5));
+            fill_rect(&mut image, left, top + row, bar_width, 1, colour);
+        }
+        if self.bar_length > 0 {
+            outline_rect(
+                &mut image,
+                left,
+                top,
+                bar_width,
+                self.bar_length,
+                self.text_colour,
+            );
+        }
+
+        let (min, length) = (self.value_range.from, self.value_range.length());
+        for (value, label) in self.ticks() {
+            let t = if length != 0.0 {
+                (value - min) / length
+            } else {
+                0.5
+            };
+            let y = top + ((1.0 - t) * self.bar_length.saturating_sub(1) as f32).round() as u32;
+            fill_rect(
+                &mut image,
+                left + bar_width,
+                y,
+                TICK_LENGTH * scale,
+                scale.max(2) / 2,
+                self.text_colour,
+            );
+            draw_text(
+                &mut image,
+                &label,
+                i64::from(left + bar_width + (TICK_LENGTH + GAP) * scale),
+                i64::from(y) - i64::from(text_height(scale) / 2),
+                scale,
+                self.text_colour,
+            );
+        }
+
+        let mut y = top + self.bar_length + text_height(scale) / 2 + GAP * scale;
+        let swatch = SWATCH_SIZE * scale;
+        for (colour, label) in self.swatch_labels() {
+            fill_rect(&mut image, left, y, swatch, swatch, to_rgba(colour));
+            outline_rect(&mut image, left, y, swatch, swatch, self.text_colour);
+            draw_text(
+                &mut image,
+                &label,
+                i64::from(left + swatch + GAP * scale),
+                i64::from(y),
+                scale,
+                self.text_colour,
+            );
+            y += (SWATCH_SIZE + GAP) * scale;
+        }
+        image
+    }
+}
@@ -6,16 +6,34 @@ extern crate csv;
 extern crate image;
 extern crate serde;
 #[macro_use]
+extern crate serde_json;
+#[macro_use]
 extern crate serde_derive;
 extern crate bincode;
 extern crate rayon;
 
+pub mod ascii_grid;
+pub mod cache;
+pub mod colormap;
+pub mod cpu_render;
 pub mod csv_read;
 pub mod data;
+pub mod draw;
+pub mod font;
+pub mod geotiff;
+pub mod graticule;
 pub mod grid;
+pub mod grid_file;
 pub mod helper;
+pub mod ingest;
 pub mod input;
+pub mod interpolate;
+pub mod legend;
 pub mod math;
 pub mod render;
+pub mod report;
+pub mod station;
+pub mod units;
+pub mod vector;
 pub mod window;
 pub mod heatmap;
\ No newline at end of file
@@ -36,7 +36,7 @@ where
 {
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Range<T: Num> {
     pub from: T,
     pub to: T,
@@ -66,6 +66,7 @@ impl<T: Num> Point<T> {
     }
 }
 
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct RangeBox<T: Num> {
     pub horizontal: Range<T>,
     pub vertical: Range<T>,
@@ -86,6 +87,7 @@ impl<T: Num> RangeBox<T> {
     }
 }
 
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Dimensions<T: Num> {
     pub x: T,
     pub y: T,
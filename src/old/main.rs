@@ -1,15 +1,25 @@
 extern crate heat_map;
 
+use heat_map::grid_file::{GridFile, GridMetadata};
 use heat_map::heatmap::HeatMap;
 use heat_map::math::{Range, RangeBox};
 
 fn main() {
+    let range = RangeBox::new(Range::new(-180.0, 180.0), Range::new(-90.0, 90.0));
     let heat_map = HeatMap::temp_heat_map_from_bin(
         (1200, 600),
-        RangeBox::new(Range::new(-180.0, 180.0), Range::new(-90.0, 90.0)),
+        range,
         "Data.bin"
     ).unwrap();
 
     let grid = heat_map.into_option_grid().fill_values_nearest();
-    grid.save_to_bin("tempgrid.bin").unwrap();
+    let metadata = GridMetadata::new()
+        .with_variable("TAVG")
+        .with_statistic("monthly mean")
+        .with_parameter("fill", "nearest");
+    GridFile::new(grid)
+        .with_range(range)
+        .with_metadata(metadata)
+        .save("tempgrid.bin")
+        .unwrap();
 }
@@ -27,6 +27,7 @@ use glium::glutin::EventsLoop;
 use glium::index::{NoIndices, PrimitiveType::TrianglesList};
 use glium::{draw_parameters::Blend, Program, Surface};
 use grid::Grid;
+use grid_file::GridFile;
 use heatmap::HeatMap;
 use helper::*;
 use math::{Range, RangeBox};
@@ -87,10 +88,10 @@ fn render_heatmap() {
     // let horizontal = Range::new(-30.0, 75.0);
     // let vertical = Range::new(15.0, 75.0);
 
-    let horizontal = Range::new(-180.0_f32, 180.0);
-    let vertical = Range::new(-90.0_f32, 90.0);
-
-    let grid: Grid<Option<YearlyData<f32>>> = Grid::load_from_bin("tempgrid.bin").expect("failed to load the grid data");
+    let file: GridFile<Option<YearlyData<f32>>> = GridFile::load("tempgrid.bin").expect("failed to load the grid data");
+    let range = file.range.expect("tempgrid.bin has no georeference");
+    let (horizontal, vertical) = (range.horizontal, range.vertical);
+    let grid = file.grid;
     let grid: Grid<Option<f32>> = grid.into_grid_with(|yearly_data| {
         match yearly_data {
             Some(data) => data.get_month_average(1),
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// Number of example rows kept for each reason
+const DEFAULT_MAX_EXAMPLES: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RejectReason {
+    MissingDate,
+    // The date could not be parsed or has a month or day out of range
+    InvalidDate,
+    MissingCoordinate,
+    // The row has no value for the element
+    MissingValue,
+    UnparsableValue,
+    // The value was removed by the QualityFilter
+    FailedQuality,
+    // The point is outside the RangeBox of the map
+    OutOfBounds,
+    // The point is inside the RangeBox but rounds to a cell outside the grid
+    OutOfGrid,
+    // Removed by the IngestFilter
+    OutsideYears,
+    ExcludedMonth,
+    OutsideRegion,
+    OutsideElevation,
+    ExcludedStation,
+}
+
+impl RejectReason {
+    pub fn all() -> [RejectReason; 13] {
+        [
+            RejectReason::MissingDate,
+            RejectReason::InvalidDate,
+            RejectReason::MissingCoordinate,
+            RejectReason::MissingValue,
+            RejectReason::UnparsableValue,
+            RejectReason::FailedQuality,
+            RejectReason::OutOfBounds,
+            RejectReason::OutOfGrid,
+            RejectReason::OutsideYears,
+            RejectReason::ExcludedMonth,
+            RejectReason::OutsideRegion,
+            RejectReason::OutsideElevation,
+            RejectReason::ExcludedStation,
+        ]
+    }
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            RejectReason::MissingDate => "missing date",
+            RejectReason::InvalidDate => "invalid date",
+            RejectReason::MissingCoordinate => "missing coordinate",
+            RejectReason::MissingValue => "missing value",
+            RejectReason::UnparsableValue => "unparsable value",
+            RejectReason::FailedQuality => "failed quality filter",
+            RejectReason::OutOfBounds => "out of bounds",
+            RejectReason::OutOfGrid => "out of grid",
+            RejectReason::OutsideYears => "outside year range",
+            RejectReason::ExcludedMonth => "excluded month",
+            RejectReason::OutsideRegion => "outside region",
+            RejectReason::OutsideElevation => "outside elevation band",
+            RejectReason::ExcludedStation => "excluded station",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Counts how many rows were accepted and rejected while loading data, keeping a few example
+// rows for every reason a row was rejected
+#[derive(Clone, Debug)]
+pub struct IngestReport {
+    pub accepted: usize,
+    rejected: HashMap<RejectReason, usize>,
+    examples: HashMap<RejectReason, Vec<String>>,
+    max_examples: usize,
+}
+
+impl Default for IngestReport {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_EXAMPLES)
+    }
+}
+
+impl IngestReport {
+    pub fn new(max_examples: usize) -> Self {
+        Self {
+            accepted: 0,
+            rejected: HashMap::new(),
+            examples: HashMap::new(),
+            max_examples,
+        }
+    }
+
+    pub fn accept(&mut self) {
+        self.accepted += 1;
+    }
+
+    // The example is only formatted if there is still room for it
+    pub fn reject<F: FnOnce() -> String>(&mut self, reason: RejectReason, example: F) {
+        *self.rejected.entry(reason).or_insert(0) += 1;
+        let examples = self.examples.entry(reason).or_default();
+        if examples.len() < self.max_examples {
+            examples.push(example());
+        }
+    }
+
+    pub fn record<T, F: FnOnce() -> String>(
+        &mut self,
+        result: &Result<T, RejectReason>,
+        example: F,
+    ) {
+        match result {
+            Ok(_) => self.accept(),
+            Err(reason) => self.reject(*reason, example),
+        }
+    }
+
+    pub fn rejected(&self, reason: RejectReason) -> usize {
+        self.rejected.get(&reason).cloned().unwrap_or(0)
+    }
+
+    pub fn total_rejected(&self) -> usize {
+        self.rejected.values().sum()
+    }
+
+    pub fn examples(&self, reason: RejectReason) -> &[String] {
+        match self.examples.get(&reason) {
+            Some(examples) => examples,
+            None => &[],
+        }
+    }
+
+    pub fn merge(&mut self, other: IngestReport) {
+        self.accepted += other.accepted;
+        for (reason, count) in other.rejected {
+            *self.rejected.entry(reason).or_insert(0) += count;
+        }
+        for (reason, other_examples) in other.examples {
+            let examples = self.examples.entry(reason).or_default();
+            let room = self.max_examples.saturating_sub(examples.len());
+            examples.extend(other_examples.into_iter().take(room));
+        }
+    }
+}
+
+impl Display for IngestReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Accepted {} rows, rejected {} rows",
+            self.accepted,
+            self.total_rejected()
+        )?;
+        for &reason in RejectReason::all().iter() {
+            let count = self.rejected(reason);
+            if count == 0 {
+                continue;
+            }
+            writeln!(f, "  {}: {}", reason, count)?;
+            for example in self.examples(reason) {
+                writeln!(f, "    {}", example)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+#version 400
+
+// Map texture from Grid::into_normalised_texture, red is the value scaled so the range is 0 to 1
+// and green is 1 where there is a value
+uniform sampler2D map;
+uniform sampler2D bwmap;
+// Colormap::texture
+uniform sampler1D palette;
+uniform vec3 under_colour;
+uniform vec3 over_colour;
+uniform vec3 nodata_colour;
+uniform float contrast;
+uniform vec2 min_pos;
+uniform vec2 max_pos;
+
+in vec2 f_position;
+in vec2 f_tex_coord;
+
+out vec4 colour;
+
+vec3 palette_colour(in float t) {
+    if (t < 0.0) {
+        return under_colour;
+    }
+    if (t > 1.0) {
+        return over_colour;
+    }
+    int size = textureSize(palette, 0);
+    return texelFetch(palette, min(int(t * float(size)), size - 1), 0).rgb;
+}
+
+void main() {
+    ivec2 dims = textureSize(map, 0);
+    ivec2 tex_pos = ivec2(int(float(dims.x) * f_tex_coord.x), int(float(dims.y) * f_tex_coord.y));
+    vec4 value = texelFetch(map, tex_pos, 0);
+    float contrast_value = (259.0 * (contrast + 255.0))/(255.0 * (259.0 - contrast));
+    float new_brightness = contrast_value * (value.x - 0.5) + 0.5;
+    vec3 value_colour = value.y < 0.5 ? nodata_colour : palette_colour(new_brightness);
+
+    vec2 f_min_pos = (min_pos + vec2(180.0, 90)) / vec2(360, 180);
+    vec2 f_max_pos = (max_pos + vec2(180.0, 90)) / vec2(360, 180);
+    float tex_x = mix(f_min_pos.x, f_max_pos.x, f_tex_coord.x);
+    float tex_y = mix(f_min_pos.y, f_max_pos.y, f_tex_coord.y);
+    dims = textureSize(bwmap, 0);
+    tex_pos = ivec2(int(float(dims.x) * tex_x), int(float(dims.y) * tex_y));
+
+    colour = texelFetch(bwmap, tex_pos, 0).x * vec4(value_colour, 1.0);
+}
@@ -0,0 +1,17 @@
+#version 400
+
+// Colormap::texture
+uniform sampler1D palette;
+uniform float contrast;
+
+in vec2 f_position;
+in vec2 f_tex_coord;
+
+out vec4 colour;
+
+void main() {
+    float contrast_value = (259.0 * (contrast + 255.0))/(255.0 * (259.0 - contrast));
+    float new_brightness = clamp(contrast_value * (f_tex_coord.y - 0.5) + 0.5, 0.0, 1.0);
+    int size = textureSize(palette, 0);
+    colour = vec4(texelFetch(palette, min(int(new_brightness * float(size)), size - 1), 0).rgb, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 400
+
+// Image drawn over the map box with its alpha, eg. Graticule::overlay
+uniform sampler2D overlay;
+
+in vec2 f_position;
+in vec2 f_tex_coord;
+
+out vec4 colour;
+
+void main() {
+    ivec2 dims = textureSize(overlay, 0);
+    ivec2 tex_pos = ivec2(int(float(dims.x) * f_tex_coord.x), int(float(dims.y) * f_tex_coord.y));
+    colour = texelFetch(overlay, min(tex_pos, dims - 1), 0);
+}
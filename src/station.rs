@@ -0,0 +1,128 @@
+use bincode::{deserialize_from, serialize_into};
+use csv_read::read::StationRecord;
+use data::{CsvRecord, TemperaturePoint};
+use math::Point;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StationMeta {
+    pub id: String,
+    pub name: Option<String>,
+    pub position: Option<Point<f32>>,
+    pub elevation: Option<f32>,
+}
+
+impl StationMeta {
+    pub fn from_record(record: &StationRecord) -> Option<Self> {
+        let position = match (record.longitude, record.latitude) {
+            (Some(long), Some(lat)) => Some(Point::new(long, lat)),
+            _ => None,
+        };
+        Some(Self {
+            id: record.station.clone()?,
+            name: record.name.clone(),
+            position,
+            elevation: record.elevation,
+        })
+    }
+
+    pub fn from_csv_record(record: &CsvRecord) -> Option<Self> {
+        let position = match (record.longitude, record.latitude) {
+            (Some(long), Some(lat)) => Some(Point::new(long, lat)),
+            _ => None,
+        };
+        Some(Self {
+            id: record.station.clone()?,
+            name: None,
+            position,
+            elevation: record.elevation,
+        })
+    }
+}
+
+// Points store the index of their station in this table rather than the station id itself so
+// they can stay Copy and small in the point caches
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StationTable {
+    stations: Vec<StationMeta>,
+    #[serde(skip)]
+    indices: HashMap<String, u32>,
+}
+
+impl StationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The table for "Data.bin" is stored at "Data.stations.bin"
+    pub fn path_for(points_path: impl AsRef<Path>) -> PathBuf {
+        points_path.as_ref().with_extension("stations.bin")
+    }
+
+    pub fn len(&self) -> usize {
+        self.stations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+
+    pub fn stations(&self) -> &[StationMeta] {
+        &self.stations
+    }
+
+    // Returns the index of the station, adding it if its id has not been seen before
+    pub fn insert(&mut self, station: StationMeta) -> u32 {
+        if let Some(&index) = self.indices.get(&station.id) {
+            return index;
+        }
+        let index = self.stations.len() as u32;
+        self.indices.insert(station.id.clone(), index);
+        self.stations.push(station);
+        index
+    }
+
+    // Adds every station of other to this table and returns the new index of each of them
+    pub fn merge(&mut self, other: StationTable) -> Vec<u32> {
+        other
+            .stations
+            .into_iter()
+            .map(|station| self.insert(station))
+            .collect()
+    }
+
+    pub fn get(&self, index: u32) -> Option<&StationMeta> {
+        self.stations.get(index as usize)
+    }
+
+    pub fn index_of(&self, id: &str) -> Option<u32> {
+        self.indices.get(id).cloned()
+    }
+
+    pub fn find(&self, id: &str) -> Option<&StationMeta> {
+        self.index_of(id).and_then(|index| self.get(index))
+    }
+
+    pub fn station_of(&self, point: &TemperaturePoint) -> Option<&StationMeta> {
+        point.station.and_then(|index| self.get(index))
+    }
+
+    pub fn save_to_bin(&self, path: impl AsRef<Path>) -> Result<(), Box<Error>> {
+        let writer = BufWriter::new(File::create(path)?);
+        serialize_into(writer, &self)?;
+        Ok(())
+    }
+
+    pub fn load_from_bin(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut table: StationTable = deserialize_from(reader)?;
+        for (i, station) in table.stations.iter().enumerate() {
+            table.indices.insert(station.id.clone(), i as u32);
+        }
+        Ok(table)
+    }
+}
@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Quantity {
+    Temperature,
+    // Precipitation, snow depth and elevation
+    Length,
+    Speed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+    Millimetre,
+    Inch,
+    Metre,
+    Foot,
+    MetresPerSecond,
+    MilesPerHour,
+    Knots,
+}
+
+impl Unit {
+    pub fn quantity(&self) -> Quantity {
+        match self {
+            Unit::Celsius | Unit::Fahrenheit | Unit::Kelvin => Quantity::Temperature,
+            Unit::Millimetre | Unit::Inch | Unit::Metre | Unit::Foot => Quantity::Length,
+            Unit::MetresPerSecond | Unit::MilesPerHour | Unit::Knots => Quantity::Speed,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+            Unit::Kelvin => "K",
+            Unit::Millimetre => "mm",
+            Unit::Inch => "in",
+            Unit::Metre => "m",
+            Unit::Foot => "ft",
+            Unit::MetresPerSecond => "m/s",
+            Unit::MilesPerHour => "mph",
+            Unit::Knots => "kn",
+        }
+    }
+
+    // Units of GSOM elements when the data is requested in metric
+    pub fn gsom_metric(element: &str) -> Option<Unit> {
+        match element {
+            "TAVG" | "TMAX" | "TMIN" | "EMXT" | "EMNT" | "HTDD" | "CLDD" => Some(Unit::Celsius),
+            "PRCP" | "SNOW" | "EMXP" | "EMSN" | "SNWD" | "EVAP" => Some(Unit::Millimetre),
+            "AWND" | "WSF1" | "WSF2" | "WSF5" | "WSFG" | "WSFM" => Some(Unit::MetresPerSecond),
+            "ELEVATION" => Some(Unit::Metre),
+            _ => None,
+        }
+    }
+
+    // GSOM elements that are sums of differences from a base temperature, such as heating degree
+    // days, so they are converted with convert_interval rather than convert
+    pub fn gsom_is_interval(element: &str) -> bool {
+        matches!(element, "HTDD" | "CLDD")
+    }
+
+    // Value of one unit in the base unit of the quantity (Celsius degrees, metres, m/s)
+    fn scale(&self) -> f32 {
+        match self {
+            Unit::Celsius | Unit::Kelvin => 1.0,
+            Unit::Fahrenheit => 5.0 / 9.0,
+            Unit::Millimetre => 0.001,
+            Unit::Inch => 0.0254,
+            Unit::Metre => 1.0,
+            Unit::Foot => 0.3048,
+            Unit::MetresPerSecond => 1.0,
+            Unit::MilesPerHour => 0.44704,
+            Unit::Knots => 0.514_444,
+        }
+    }
+
+    // Base unit value of zero in this unit, only temperatures have an offset
+    fn offset(&self) -> f32 {
+        match self {
+            Unit::Kelvin => -273.15,
+            Unit::Fahrenheit => -160.0 / 9.0,
+            _ => 0.0,
+        }
+    }
+
+    pub fn check_compatible(&self, other: Unit) -> Result<(), UnitErr> {
+        if self.quantity() == other.quantity() {
+            Ok(())
+        } else {
+            Err(UnitErr::Incompatible(*self, other))
+        }
+    }
+
+    // Converts an absolute value such as a temperature reading
+    pub fn convert(&self, value: f32, to: Unit) -> Result<f32, UnitErr> {
+        self.check_compatible(to)?;
+        if *self == to {
+            return Ok(value);
+        }
+        let base = value * self.scale() + self.offset();
+        Ok((base - to.offset()) / to.scale())
+    }
+
+    // Converts a difference between two values, such as a standard deviation or a range, which
+    // ignores the offset between temperature scales
+    pub fn convert_interval(&self, value: f32, to: Unit) -> Result<f32, UnitErr> {
+        self.check_compatible(to)?;
+        Ok(value * self.scale() / to.scale())
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+impl FromStr for Unit {
+    type Err = UnitErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unit = match s.trim().to_lowercase().as_str() {
+            "c" | "°c" | "degc" | "celsius" => Unit::Celsius,
+            "f" | "°f" | "degf" | "fahrenheit" => Unit::Fahrenheit,
+            "k" | "kelvin" => Unit::Kelvin,
+            "mm" | "millimetre" | "millimeter" => Unit::Millimetre,
+            "in" | "inch" | "inches" => Unit::Inch,
+            "m" | "metre" | "meter" | "metres" | "meters" => Unit::Metre,
+            "ft" | "foot" | "feet" => Unit::Foot,
+            "m/s" | "ms-1" | "mps" => Unit::MetresPerSecond,
+            "mph" | "mi/h" => Unit::MilesPerHour,
+            "kn" | "kt" | "kts" | "knot" | "knots" => Unit::Knots,
+            _ => return Err(UnitErr::Unknown(String::from(s))),
+        };
+        Ok(unit)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnitErr {
+    Unknown(String),
+    // The values have no unit to convert from
+    Missing,
+    // The units measure different quantities and can't be converted
+    Incompatible(Unit, Unit),
+    // The units measure the same quantity but the values have to be converted first
+    Mismatch(Unit, Unit),
+    // Grids of different sizes, as (horizontal, vertical), can't be combined cell by cell
+    SizeMismatch((usize, usize), (usize, usize)),
+}
+
+impl Display for UnitErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UnitErr::Unknown(unit) => write!(f, "Unknown unit \"{}\"", unit),
+            UnitErr::Missing => write!(f, "Values have no unit to convert from"),
+            UnitErr::Incompatible(from, to) => {
+                write!(f, "Cannot convert between {} and {}", from, to)
+            }
+            UnitErr::Mismatch(first, second) => write!(
+                f,
+                "Values in {} and {} have to be converted to the same unit first",
+                first, second
+            ),
+            UnitErr::SizeMismatch(first, second) => write!(
+                f,
+                "Grids of {}x{} and {}x{} cells have to be the same size",
+                first.0, first.1, second.0, second.1
+            ),
+        }
+    }
+}
+
+impl Error for UnitErr {}
+
+// Checks two optional units can be used together without converting. Values of different
+// quantities (eg. rain against temperature) can be compared, values of the same quantity have
+// to be in the same unit
+pub fn check_same_units(first: Option<Unit>, second: Option<Unit>) -> Result<(), UnitErr> {
+    match (first, second) {
+        (Some(first), Some(second)) => {
+            if first.quantity() == second.quantity() && first != second {
+                Err(UnitErr::Mismatch(first, second))
+            } else {
+                Ok(())
+            }
+        }
+        _ => Ok(()),
+    }
+}
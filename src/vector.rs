@@ -0,0 +1,347 @@
+use draw::blend_pixel;
+use image::{Rgba, RgbaImage};
+use math::{Point, RangeBox};
+use serde_json;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+// Shapefile shape types with lines, the Z and M variants have extra values after the points
+const LINE_TYPES: [i32; 6] = [3, 5, 13, 15, 23, 25];
+const SHAPEFILE_CODE: i32 = 9994;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VectorErr {
+    // The extension isn't .geojson, .json or .shp
+    UnknownFormat(String),
+    InvalidGeoJson(String),
+    NotAShapefile,
+    Truncated,
+}
+
+impl Display for VectorErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            VectorErr::UnknownFormat(path) => write!(f, "Unknown vector format of \"{}\"", path),
+            VectorErr::InvalidGeoJson(reason) => write!(f, "Invalid GeoJSON: {}", reason),
+            VectorErr::NotAShapefile => write!(f, "File is not an ESRI Shapefile"),
+            VectorErr::Truncated => write!(f, "Shapefile ends in the middle of a record"),
+        }
+    }
+}
+
+impl Error for VectorErr {}
+
+// Lines in longitude and latitude drawn over the map, eg. coastlines or country borders.
+// Polygons are drawn as their rings, points are ignored
+#[derive(Clone, Debug)]
+pub struct VectorLayer {
+    pub lines: Vec<Vec<Point<f32>>>,
+    pub colour: Rgba<u8>,
+    // In pixels, lines are anti-aliased so they can be thinner than a pixel
+    pub width: f32,
+}
+
+// Coordinates of a GeoJSON position, only longitude and latitude are used
+fn position(value: &Value) -> Result<Point<f32>, VectorErr> {
+    let invalid = || VectorErr::InvalidGeoJson(format!("invalid position {}", value));
+    let coordinates = value.as_array().ok_or_else(invalid)?;
+    if coordinates.len() < 2 {
+        return Err(invalid());
+    }
+    let x = coordinates[0].as_f64().ok_or_else(invalid)?;
+    let y = coordinates[1].as_f64().ok_or_else(invalid)?;
+    Ok(Point::new(x as f32, y as f32))
+}
+
+fn line(value: &Value) -> Result<Vec<Point<f32>>, VectorErr> {
+    value
+        .as_array()
+        .ok_or_else(|| VectorErr::InvalidGeoJson(String::from("line is not an array")))?
+        .iter()
+        .map(position)
+        .collect()
+}
+
+// Each element of the array nested depth deep is a line, eg. depth 1 for a MultiLineString
+// and 2 for a MultiPolygon
+fn lines(value: &Value, depth: usize, out: &mut Vec<Vec<Point<f32>>>) -> Result<(), VectorErr> {
+    if depth == 0 {
+        out.push(line(value)?);
+        return Ok(());
+    }
+    let items = value
+        .as_array()
+        .ok_or_else(|| VectorErr::InvalidGeoJson(String::from("coordinates are not an array")))?;
+    for item in items {
+        lines(item, depth - 1, out)?;
+    }
+    Ok(())
+}
+
+// Reads the lines of any GeoJSON object, ie. a FeatureCollection, Feature or geometry
+fn geojson_lines(value: &Value, out: &mut Vec<Vec<Point<f32>>>) -> Result<(), VectorErr> {
+    let kind = value
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| VectorErr::InvalidGeoJson(String::from("object has no type")))?;
+    let coordinates = || {
+        value
+            .get("coordinates")
+            .ok_or_else(|| VectorErr::InvalidGeoJson(format!("{} has no coordinates", kind)))
+    };
+    let members = |name: &str| {
+        value
+            .get(name)
+            .and_then(Value::as_array)
+            .ok_or_else(|| VectorErr::InvalidGeoJson(format!("{} has no {}", kind, name)))
+    };
+    match kind {
+        "FeatureCollection" => {
+            for feature in members("features")? {
+                geojson_lines(feature, out)?;
+            }
+        }
+        "Feature" => match value.get("geometry") {
+            Some(Value::Null) | None => (),
+            Some(geometry) => geojson_lines(geometry, out)?,
+        },
+        "GeometryCollection" => {
+            for geometry in members("geometries")? {
+                geojson_lines(geometry, out)?;
+            }
+        }
+        "LineString" => lines(coordinates()?, 0, out)?,
+        "MultiLineString" | "Polygon" => lines(coordinates()?, 1, out)?,
+        "MultiPolygon" => lines(coordinates()?, 2, out)?,
+        "Point" | "MultiPoint" => (),
+        _ => return Err(VectorErr::InvalidGeoJson(format!("unknown type {}", kind))),
+    }
+    Ok(())
+}
+
+fn read_i32(bytes: &[u8], offset: usize, big_endian: bool) -> Result<i32, VectorErr> {
+    let slice = bytes.get(offset..offset + 4).ok_or(VectorErr::Truncated)?;
+    let array = [slice[0], slice[1], slice[2], slice[3]];
+    Ok(if big_endian {
+        i32::from_be_bytes(array)
+    } else {
+        i32::from_le_bytes(array)
+    })
+}
+
+fn read_f64(bytes: &[u8], offset: usize) -> Result<f64, VectorErr> {
+    let slice = bytes.get(offset..offset + 8).ok_or(VectorErr::Truncated)?;
+    let mut array = [0u8; 8];
+    array.copy_from_slice(slice);
+    Ok(f64::from_le_bytes(array))
+}
+
+// Parts of a PolyLine or Polygon record, content starts at the shape type
+fn shape_lines(content: &[u8], out: &mut Vec<Vec<Point<f32>>>) -> Result<(), VectorErr> {
+    // Shape type then the bounding box
+    let parts = read_i32(content, 36, false)?.max(0) as usize;
+    let points = read_i32(content, 40, false)?.max(0) as usize;
+    let starts = (0..parts)
+        .map(|part| read_i32(content, 44 + part * 4, false).map(|start| start.max(0) as usize))
+        .collect::<Result<Vec<usize>, VectorErr>>()?;
+    let first_point = 44 + parts * 4;
+    for (part, &start) in starts.iter().enumerate() {
+        let end = starts.get(part + 1).cloned().unwrap_or(points).min(points);
+        let mut line = Vec::with_capacity(end.saturating_sub(start));
+        for point in start..end {
+            let offset = first_point + point * 16;
+            let x = read_f64(content, offset)?;
+            let y = read_f64(content, offset + 8)?;
+            line.push(Point::new(x as f32, y as f32));
+        }
+        out.push(line);
+    }
+    Ok(())
+}
+
+impl VectorLayer {
+    pub fn new(lines: Vec<Vec<Point<f32>>>) -> Self {
+        Self {
+            lines,
+            colour: Rgba {
+                data: [255, 255, 255, 255],
+            },
+            width: 1.0,
+        }
+    }
+
+    pub fn with_colour(mut self, colour: Rgba<u8>) -> Self {
+        self.colour = colour;
+        self
+    }
+
+    pub fn with_width(mut self, width: f32) -> Self {
+        self.width = width;
+        self
+    }
+
+    // Picks the reader from the extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("geojson") | Some("json") => Self::from_geojson(path),
+            Some("shp") => Self::from_shapefile(path),
+            _ => Err(Box::new(VectorErr::UnknownFormat(
+                path.as_ref().display().to_string(),
+            ))),
+        }
+    }
+
+    pub fn from_geojson(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let value: Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let mut lines = Vec::new();
+        geojson_lines(&value, &mut lines)?;
+        Ok(Self::new(lines))
+    }
+
+    // Reads the .shp file of a Shapefile, the .dbf attributes aren't needed to draw the lines.
+    // Coordinates are assumed to be longitude and latitude
+    pub fn from_shapefile(path: impl AsRef<Path>) -> Result<Self, Box<Error>> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        if bytes.len() < 100 || read_i32(&bytes, 0, true)? != SHAPEFILE_CODE {
+            return Err(Box::new(VectorErr::NotAShapefile));
+        }
+        let mut lines = Vec::new();
+        let mut offset = 100;
+        while offset + 8 <= bytes.len() {
+            // Record headers are big endian and give the length in 16 bit words
+            let length = read_i32(&bytes, offset + 4, true)?.max(0) as usize * 2;
+            let content = bytes
+                .get(offset + 8..offset + 8 + length)
+                .ok_or(VectorErr::Truncated)?;
+            let shape_type = read_i32(content, 0, false)?;
+            // Null shapes and points have nothing to draw
+            if LINE_TYPES.contains(&shape_type) {
+                shape_lines(content, &mut lines)?;
+            }
+            offset += 8 + length;
+        }
+        Ok(Self::new(lines))
+    }
+
+    // Draws the lines within the range over an image of the map covering it. Coverage is
+    // gathered for the whole layer first so joins between segments aren't blended twice
+    pub fn draw(&self, image: &mut RgbaImage, range: &RangeBox<f32>) {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 || self.width <= 0.0 {
+            return;
+        }
+        let scale_x = width as f32 / range.horizontal.length();
+        let scale_y = height as f32 / range.vertical.length();
+        // Pixel position with y from the top, pixel centres are at 0.5
+        let to_pixel = |point: Point<f32>| {
+            Point::new(
+                (point.x - range.horizontal.from) * scale_x,
+                (range.vertical.to - point.y) * scale_y,
+            )
+        };
+        let mut coverage = vec![0f32; (width * height) as usize];
+        for line in &self.lines {
+            for segment in line.windows(2) {
+                if let Some((start, end)) = clip_segment(segment[0], segment[1], range) {
+                    cover_segment(
+                        &mut coverage,
+                        width,
+                        height,
+                        to_pixel(start),
+                        to_pixel(end),
+                        self.width,
+                    );
+                }
+            }
+        }
+        for (index, &amount) in coverage.iter().enumerate() {
+            if amount > 0.0 {
+                let (x, y) = (index as u32 % width, index as u32 / width);
+                blend_pixel(image, i64::from(x), i64::from(y), self.colour, amount);
+            }
+        }
+    }
+}
+
+// Liang-Barsky clipping of the segment to the range, None when it lies outside
+pub fn clip_segment(
+    start: Point<f32>,
+    end: Point<f32>,
+    range: &RangeBox<f32>,
+) -> Option<(Point<f32>, Point<f32>)> {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let (mut from, mut to) = (0f32, 1f32);
+    let edges = [
+        (-dx, start.x - range.horizontal.from),
+        (dx, range.horizontal.to - start.x),
+        (-dy, start.y - range.vertical.from),
+        (dy, range.vertical.to - start.y),
+    ];
+    for &(p, q) in &edges {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                from = from.max(t);
+            } else {
+                to = to.min(t);
+            }
+        }
+    }
+    if from > to {
+        return None;
+    }
+    Some((
+        Point::new(start.x + from * dx, start.y + from * dy),
+        Point::new(start.x + to * dx, start.y + to * dy),
+    ))
+}
+
+// Adds the coverage of a line of the width between two pixel positions, from the distance of
+// each pixel centre to the segment
+fn cover_segment(
+    coverage: &mut [f32],
+    width: u32,
+    height: u32,
+    start: Point<f32>,
+    end: Point<f32>,
+    line_width: f32,
+) {
+    let reach = line_width / 2.0 + 0.5;
+    let left = ((start.x.min(end.x) - reach).floor().max(0.0)) as u32;
+    let right = ((start.x.max(end.x) + reach).ceil().min(width as f32)) as u32;
+    let top = ((start.y.min(end.y) - reach).floor().max(0.0)) as u32;
+    let bottom = ((start.y.max(end.y) + reach).ceil().min(height as f32)) as u32;
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let length_squared = dx * dx + dy * dy;
+    for y in top..bottom {
+        for x in left..right {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let t = if length_squared > 0.0 {
+                (((px - start.x) * dx + (py - start.y) * dy) / length_squared).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let (cx, cy) = (start.x + t * dx - px, start.y + t * dy - py);
+            let distance = (cx * cx + cy * cy).sqrt();
+            // Lines thinner than a pixel are fainter rather than narrower
+            let amount = (reach - distance).clamp(0.0, 1.0) * line_width.min(1.0);
+            let cell = &mut coverage[(x + y * width) as usize];
+            *cell = cell.max(amount);
+        }
+    }
+}